@@ -0,0 +1,255 @@
+//! Compares the data modelling strategies by running the same scenario on
+//! every backend, timing one phase of it at a time. The number of
+//! allocations made in each phase is printed before it's benchmarked.
+
+use criterion::{criterion_group, criterion_main, BenchmarkGroup, Criterion};
+use rust_data_modelling::{
+    cell::CellPoolBackend,
+    ghost_pool::GhostPoolBackend,
+    ghost_rc::GhostRcBackend,
+    model::{Model, ModelBackend, Scenario},
+    ref_cell::RefCellBackend,
+    ref_count::RcBackend,
+    static_cell::StaticPoolBackend,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::HashSet,
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const PLAYER_COUNT: usize = 1000;
+
+/// Keeps the friend lists short, as in a typical game.
+const MAX_FRIENDS: usize = 5;
+
+const HEALTH_ROUNDS: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Create,
+    MakeFriends,
+    UpdateHealth,
+    IterateFriends,
+    Remove,
+}
+
+impl Phase {
+    const ALL: [Phase; 5] = [
+        Phase::Create,
+        Phase::MakeFriends,
+        Phase::UpdateHealth,
+        Phase::IterateFriends,
+        Phase::Remove,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Create => "create",
+            Phase::MakeFriends => "make_friends",
+            Phase::UpdateHealth => "update_health",
+            Phase::IterateFriends => "iterate_friends",
+            Phase::Remove => "remove",
+        }
+    }
+}
+
+/// A xorshift generator, so every backend gets the same friendship graph
+/// without pulling in `rand`.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+/// Random friendships between distinct players, with no player getting more
+/// than `MAX_FRIENDS` friends.
+fn friend_pairs(player_count: usize) -> Vec<(usize, usize)> {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let mut friend_counts = vec![0; player_count];
+    let mut seen = HashSet::new();
+    let mut pairs = Vec::new();
+
+    for _ in 0..player_count * MAX_FRIENDS {
+        let a = rng.below(player_count);
+        let b = rng.below(player_count);
+
+        if a == b
+            || friend_counts[a] >= MAX_FRIENDS
+            || friend_counts[b] >= MAX_FRIENDS
+            || !seen.insert((a.min(b), a.max(b)))
+        {
+            continue;
+        }
+
+        friend_counts[a] += 1;
+        friend_counts[b] += 1;
+        pairs.push((a, b));
+    }
+
+    pairs
+}
+
+#[derive(Default)]
+struct Sample {
+    time: Duration,
+    allocations: usize,
+}
+
+/// Creates players, makes them friends, updates and reads their health and
+/// removes them again, only measuring `phase`.
+///
+/// The repeatable phases are run `iterations` times on the same game, the
+/// others once.
+struct Measure<'a> {
+    phase: Phase,
+    iterations: u64,
+    pairs: &'a [(usize, usize)],
+}
+
+impl Measure<'_> {
+    fn measure(&self, phase: Phase, sample: &mut Sample, f: impl FnOnce()) {
+        if phase != self.phase {
+            return f();
+        }
+
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+        f();
+        sample.time += start.elapsed();
+        sample.allocations += ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    }
+
+    fn repeat(&self, phase: Phase) -> u64 {
+        if phase == self.phase {
+            self.iterations
+        } else {
+            1
+        }
+    }
+}
+
+impl Scenario for Measure<'_> {
+    type Output = Sample;
+
+    fn run<M: Model>(self, m: &mut M) -> Sample {
+        let mut sample = Sample::default();
+        let mut players = Vec::with_capacity(PLAYER_COUNT);
+
+        self.measure(Phase::Create, &mut sample, || {
+            for i in 0..PLAYER_COUNT {
+                players.push(m.create_player("Player", i as i32).unwrap());
+            }
+        });
+
+        self.measure(Phase::MakeFriends, &mut sample, || {
+            for &(a, b) in self.pairs {
+                m.make_friends(&players[a], &players[b]).unwrap();
+            }
+        });
+
+        for _ in 0..self.repeat(Phase::UpdateHealth) {
+            self.measure(Phase::UpdateHealth, &mut sample, || {
+                for _ in 0..HEALTH_ROUNDS {
+                    for p in players.iter() {
+                        let health = m.health(p);
+                        m.set_health(p, health + 1);
+                    }
+                }
+            });
+        }
+
+        for _ in 0..self.repeat(Phase::IterateFriends) {
+            self.measure(Phase::IterateFriends, &mut sample, || {
+                let mut total = 0;
+
+                for p in players.iter() {
+                    m.for_each_friend(p, |f| total += m.health(f));
+                }
+
+                black_box(total);
+            });
+        }
+
+        self.measure(Phase::Remove, &mut sample, || {
+            for p in players.iter() {
+                m.remove_player(p).unwrap();
+            }
+        });
+
+        sample
+    }
+}
+
+fn bench_backend<B: ModelBackend>(
+    group: &mut BenchmarkGroup<'_, criterion::measurement::WallTime>,
+    phase: Phase,
+    pairs: &[(usize, usize)],
+) {
+    let measure = |iterations| Measure {
+        phase,
+        iterations,
+        pairs,
+    };
+
+    let allocations = B::run(measure(1)).allocations;
+    println!("{}/{}: {} allocations", phase.name(), B::NAME, allocations);
+
+    group.bench_function(B::NAME, |b| {
+        b.iter_custom(|iterations| match phase {
+            Phase::UpdateHealth | Phase::IterateFriends => B::run(measure(iterations)).time,
+            _ => (0..iterations).map(|_| B::run(measure(1)).time).sum(),
+        })
+    });
+}
+
+fn strategies(c: &mut Criterion) {
+    let pairs = friend_pairs(PLAYER_COUNT);
+
+    for &phase in Phase::ALL.iter() {
+        let mut group = c.benchmark_group(phase.name());
+        group.sample_size(20);
+
+        bench_backend::<RcBackend>(&mut group, phase, &pairs);
+        bench_backend::<RefCellBackend>(&mut group, phase, &pairs);
+        bench_backend::<CellPoolBackend>(&mut group, phase, &pairs);
+        bench_backend::<StaticPoolBackend>(&mut group, phase, &pairs);
+        bench_backend::<GhostRcBackend>(&mut group, phase, &pairs);
+        bench_backend::<GhostPoolBackend>(&mut group, phase, &pairs);
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, strategies);
+criterion_main!(benches);
@@ -0,0 +1,151 @@
+//! Derive macros for the `Clear` and `ClearMut` traits in
+//! `rust_data_modelling::clear`.
+//!
+//! Each field is cleared through its own impl, in declaration order. A field
+//! can opt out with `#[clear(skip)]`, or be cleared by a function taking a
+//! reference to it with `#[clear(with = "path::to::function")]`.
+//!
+//! A function taking a reference to the whole struct can be run before the
+//! fields are cleared with `#[clear(before = "path::to::function")]` on the
+//! struct, e.g. to unlink it from other items while its fields are intact.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Error, GenericParam, Index, LitStr, Path,
+    Result,
+};
+
+#[proc_macro_derive(Clear, attributes(clear))]
+pub fn derive_clear(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input, Mode::Shared)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(ClearMut, attributes(clear))]
+pub fn derive_clear_mut(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input, Mode::Mut)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Shared,
+    Mut,
+}
+
+enum FieldClear {
+    Skip,
+    Default,
+    With(Path),
+}
+
+fn expand(mut input: DeriveInput, mode: Mode) -> Result<TokenStream2> {
+    let (trait_path, method, receiver, borrow) = match mode {
+        Mode::Shared => (
+            quote!(::rust_data_modelling::clear::Clear),
+            format_ident!("clear"),
+            quote!(&self),
+            quote!(&),
+        ),
+        Mode::Mut => (
+            quote!(::rust_data_modelling::clear::ClearMut),
+            format_ident!("clear_mut"),
+            quote!(&mut self),
+            quote!(&mut),
+        ),
+    };
+
+    let fields = match &input.data {
+        Data::Struct(s) => &s.fields,
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "Clear can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut stmts = Vec::new();
+
+    if let Some(path) = before_hook(&input.attrs)? {
+        stmts.push(quote!(#path(#borrow *self);));
+    }
+
+    for (i, field) in fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => quote!(#ident),
+            None => {
+                let index = Index::from(i);
+                quote!(#index)
+            }
+        };
+
+        match field_clear(&field.attrs)? {
+            FieldClear::Skip => {}
+            FieldClear::Default => stmts.push(quote!(#trait_path::#method(#borrow self.#member);)),
+            FieldClear::With(path) => stmts.push(quote!(#path(#borrow self.#member);)),
+        }
+    }
+
+    for param in input.generics.params.iter_mut() {
+        if let GenericParam::Type(t) = param {
+            t.bounds.push(parse_quote!(#trait_path));
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #trait_path for #name #ty_generics #where_clause {
+            fn #method(#receiver) {
+                #(#stmts)*
+            }
+        }
+    })
+}
+
+fn field_clear(attrs: &[syn::Attribute]) -> Result<FieldClear> {
+    let mut result = FieldClear::Default;
+
+    for attr in attrs.iter().filter(|a| a.path().is_ident("clear")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                result = FieldClear::Skip;
+                Ok(())
+            } else if meta.path.is_ident("with") {
+                let s: LitStr = meta.value()?.parse()?;
+                result = FieldClear::With(s.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `skip` or `with = \"...\"`"))
+            }
+        })?;
+    }
+
+    Ok(result)
+}
+
+fn before_hook(attrs: &[syn::Attribute]) -> Result<Option<Path>> {
+    let mut result = None;
+
+    for attr in attrs.iter().filter(|a| a.path().is_ident("clear")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("before") {
+                let s: LitStr = meta.value()?.parse()?;
+                result = Some(s.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `before = \"...\"`"))
+            }
+        })?;
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,248 @@
+use crate::{
+    cell_pool::{slot_index, PoolError},
+    clear::Clear,
+};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+type Index = u32;
+type Generation = u32;
+
+/// Marks the end of the free list.
+const NONE: Index = Index::MAX;
+
+/// Like in `CellPool`, a slot's generation is odd while it's allocated.
+fn is_live(generation: Generation) -> bool {
+    generation & 1 == 1
+}
+
+/// The head of the free list is an index tagged with a counter that's bumped
+/// on every change, so a compare and swap fails if the head has been popped
+/// and pushed back in between (the ABA problem).
+fn pack(tag: u32, index: Index) -> u64 {
+    ((tag as u64) << 32) | index as u64
+}
+
+fn unpack(head: u64) -> (u32, Index) {
+    ((head >> 32) as u32, head as Index)
+}
+
+/// A fixed size pool that can be allocated from and freed to by many threads
+/// at once, using a lock-free stack of free slots.
+///
+/// Unlike `CellPool` it doesn't link the allocated items into a list, so
+/// `iter` scans all slots instead.
+pub struct AtomicPool<T> {
+    items: Vec<T>,
+    next: Vec<AtomicU32>,
+    generation: Vec<AtomicU32>,
+    free_head: AtomicU64,
+    size: AtomicUsize,
+}
+
+impl<T: Default> AtomicPool<T> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity < NONE as usize, "Capacity too large!");
+        let capacity = capacity as Index;
+
+        Self {
+            items: (0..capacity).map(|_| Default::default()).collect(),
+            next: (0..capacity)
+                .map(|i| AtomicU32::new(if i + 1 < capacity { i + 1 } else { NONE }))
+                .collect(),
+            generation: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+            free_head: AtomicU64::new(pack(0, if capacity > 0 { 0 } else { NONE })),
+            size: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T> AtomicPool<T> {
+    pub fn alloc(&self) -> Result<&T, PoolError> {
+        let mut head = self.free_head.load(Ordering::Acquire);
+
+        let index = loop {
+            let (tag, index) = unpack(head);
+
+            if index == NONE {
+                return Err(PoolError::Exhausted {
+                    capacity: self.capacity(),
+                });
+            }
+
+            // May be stale if another thread pops `index` first, but then the
+            // tag has changed and the exchange fails
+            let next = self.next[index as usize].load(Ordering::Relaxed);
+            let new_head = pack(tag.wrapping_add(1), next);
+
+            match self.free_head.compare_exchange_weak(
+                head,
+                new_head,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break index as usize,
+                Err(h) => head = h,
+            }
+        };
+
+        self.generation[index].fetch_add(1, Ordering::AcqRel);
+        self.size.fetch_add(1, Ordering::Relaxed);
+        Ok(&self.items[index])
+    }
+
+    /// Frees `p` after resetting it with `clear`. Only one of several threads
+    /// freeing the same item at once succeeds, the others get `DoubleFree`.
+    pub fn free_with(&self, p: &T, clear: impl FnOnce(&T)) -> Result<(), PoolError> {
+        let i = slot_index(&self.items, p).ok_or(PoolError::ForeignItem)?;
+        let g = &self.generation[i];
+        let mut generation = g.load(Ordering::Acquire);
+
+        // Claim the slot by making its generation even
+        loop {
+            if !is_live(generation) {
+                return Err(PoolError::DoubleFree { index: i });
+            }
+
+            match g.compare_exchange_weak(
+                generation,
+                generation.wrapping_add(1),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(x) => generation = x,
+            }
+        }
+
+        clear(&self.items[i]);
+
+        // Push it on the free list
+        let mut head = self.free_head.load(Ordering::Acquire);
+
+        loop {
+            let (tag, index) = unpack(head);
+            self.next[i].store(index, Ordering::Relaxed);
+
+            match self.free_head.compare_exchange_weak(
+                head,
+                pack(tag.wrapping_add(1), i as Index),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(h) => head = h,
+            }
+        }
+
+        self.size.fetch_sub(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Iterates over the items that are allocated at the time their slot is
+    /// visited.
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.items
+            .iter()
+            .zip(self.generation.iter())
+            .filter(|(_, g)| is_live(g.load(Ordering::Acquire)))
+            .map(|(x, _)| x)
+    }
+}
+
+impl<T: Clear> AtomicPool<T> {
+    pub fn free(&self, p: &T) -> Result<(), PoolError> {
+        self.free_with(p, T::clear)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Barrier, thread};
+
+    const THREADS: usize = 8;
+
+    #[test]
+    fn concurrent_alloc_and_free() {
+        let pool = AtomicPool::<AtomicUsize>::new(64);
+
+        thread::scope(|s| {
+            for t in 1..=THREADS {
+                let pool = &pool;
+
+                s.spawn(move || {
+                    for _ in 0..1000 {
+                        let items: Vec<_> = (0..4).filter_map(|_| pool.alloc().ok()).collect();
+
+                        for x in items.iter() {
+                            assert_eq!(x.swap(t, Ordering::Relaxed), 0);
+                        }
+
+                        for x in items {
+                            assert_eq!(x.load(Ordering::Relaxed), t);
+                            pool.free_with(x, |x| x.store(0, Ordering::Relaxed))
+                                .unwrap();
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(pool.len(), 0);
+
+        for _ in 0..pool.capacity() {
+            pool.alloc().unwrap();
+        }
+
+        assert_eq!(
+            pool.alloc().err(),
+            Some(PoolError::Exhausted { capacity: 64 })
+        );
+    }
+
+    #[test]
+    fn concurrent_double_free() {
+        let pool = AtomicPool::<AtomicUsize>::new(1);
+        let barrier = Barrier::new(THREADS);
+
+        for _ in 0..100 {
+            let x = pool.alloc().unwrap();
+
+            let freed = thread::scope(|s| {
+                let threads: Vec<_> = (0..THREADS)
+                    .map(|_| {
+                        s.spawn(|| {
+                            barrier.wait();
+                            pool.free_with(x, |_| {})
+                        })
+                    })
+                    .collect();
+
+                threads
+                    .into_iter()
+                    .map(|t| t.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+
+            assert_eq!(freed.iter().filter(|x| x.is_ok()).count(), 1);
+
+            assert!(freed
+                .iter()
+                .all(|x| matches!(x, Ok(()) | Err(PoolError::DoubleFree { index: 0 }))));
+
+            assert_eq!(pool.len(), 0);
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use crate::{
+    cell_pool::PoolError,
+    ghost_cell::{GhostCell, GhostToken},
+};
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    mem,
+};
+
+/// Invariant in `'brand`, like `GhostToken`.
+type Brand<'brand> = PhantomData<fn(&'brand ()) -> &'brand ()>;
+
+/// Refers to an item in the `GhostPool` with the same brand. As each brand
+/// has at most one pool, and a pool never shrinks, the index is always in
+/// bounds. The item may have been freed though, and its slot reused, so the
+/// index also holds the generation of the slot, which changes when it's freed.
+pub struct BrandedIndex<'brand, T> {
+    index: u32,
+    generation: u32,
+    _brand: Brand<'brand>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'brand, T> BrandedIndex<'brand, T> {
+    fn new(index: u32, generation: u32) -> Self {
+        Self {
+            index,
+            generation,
+            _brand: PhantomData,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+impl<'brand, T> Copy for BrandedIndex<'brand, T> {}
+
+impl<'brand, T> Clone for BrandedIndex<'brand, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'brand, T> PartialEq for BrandedIndex<'brand, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<'brand, T> Eq for BrandedIndex<'brand, T> {}
+
+impl<'brand, T> Hash for BrandedIndex<'brand, T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<'brand, T> fmt::Debug for BrandedIndex<'brand, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BrandedIndex({}v{})", self.index, self.generation)
+    }
+}
+
+enum Slot<T> {
+    Live(T),
+    /// Links to the next free slot.
+    Free(u32),
+}
+
+/// Marks the end of the free list.
+const NONE: u32 = u32::MAX;
+
+struct Slots<T> {
+    slots: Vec<Slot<T>>,
+    /// Bumped each time a slot is freed, so the slot's item is live exactly
+    /// when the generation of an index to it matches.
+    generations: Vec<u32>,
+    first_free: u32,
+    size: usize,
+}
+
+impl<T> Slots<T> {
+    fn is_live(&self, index: BrandedIndex<'_, T>) -> bool {
+        // SAFETY: The index came from this pool, see `BrandedIndex`
+        unsafe { *self.generations.get_unchecked(index.index()) == index.generation }
+    }
+
+    fn get(&self, index: BrandedIndex<'_, T>) -> Option<&T> {
+        if !self.is_live(index) {
+            return None;
+        }
+
+        // SAFETY: See `is_live`
+        match unsafe { self.slots.get_unchecked(index.index()) } {
+            Slot::Live(x) => Some(x),
+            Slot::Free(_) => None,
+        }
+    }
+
+    fn get_mut(&mut self, index: BrandedIndex<'_, T>) -> Option<&mut T> {
+        if !self.is_live(index) {
+            return None;
+        }
+
+        // SAFETY: See `is_live`
+        match unsafe { self.slots.get_unchecked_mut(index.index()) } {
+            Slot::Live(x) => Some(x),
+            Slot::Free(_) => None,
+        }
+    }
+}
+
+/// Needed to create the `GhostPool` of a brand. It's handed out once per
+/// brand, together with its token, so no two pools can have the same brand.
+pub struct PoolBrand<'brand>(Brand<'brand>);
+
+impl<'brand> PoolBrand<'brand> {
+    /// Creates a token and a pool brand, with a brand unique to this call,
+    /// like `GhostToken::new`. The pool is created from the brand separately,
+    /// so that its items can refer to the brand.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new<R>(
+        f: impl for<'new_brand> FnOnce(PoolBrand<'new_brand>, GhostToken<'new_brand>) -> R,
+    ) -> R {
+        GhostToken::new(|token| f(PoolBrand(PhantomData), token))
+    }
+}
+
+/// A pool of items that can only be accessed with the token of its brand,
+/// like a `CellPool` of `GhostCell`s, but referring to items by
+/// `BrandedIndex`. Items are moved into the pool when allocated, so they
+/// need no `Default` value.
+///
+/// Freed slots are reused but never removed.
+pub struct GhostPool<'brand, T> {
+    slots: GhostCell<'brand, Slots<T>>,
+}
+
+impl<'brand, T> GhostPool<'brand, T> {
+    pub fn new(_brand: PoolBrand<'brand>) -> Self {
+        Self {
+            slots: GhostCell::new(Slots {
+                slots: Vec::new(),
+                generations: Vec::new(),
+                first_free: NONE,
+                size: 0,
+            }),
+        }
+    }
+
+    /// Moves `value` into a free slot, or a new one if there are none.
+    pub fn alloc(&self, token: &mut GhostToken<'brand>, value: T) -> BrandedIndex<'brand, T> {
+        let s = self.slots.borrow_mut(token);
+
+        let index = if s.first_free != NONE {
+            let index = s.first_free;
+
+            if let Slot::Free(next) = s.slots[index as usize] {
+                s.first_free = next;
+            }
+
+            s.slots[index as usize] = Slot::Live(value);
+            index
+        } else {
+            assert!(s.slots.len() < NONE as usize, "Pool full!");
+            s.slots.push(Slot::Live(value));
+            s.generations.push(0);
+            (s.slots.len() - 1) as u32
+        };
+
+        s.size += 1;
+        BrandedIndex::new(index, s.generations[index as usize])
+    }
+
+    /// Moves the item out of the pool, freeing its slot for reuse.
+    pub fn free(
+        &self,
+        token: &mut GhostToken<'brand>,
+        index: BrandedIndex<'brand, T>,
+    ) -> Result<T, PoolError> {
+        let s = self.slots.borrow_mut(token);
+
+        if !s.is_live(index) {
+            return Err(PoolError::DoubleFree {
+                index: index.index(),
+            });
+        }
+
+        let i = index.index();
+        s.generations[i] = s.generations[i].wrapping_add(1);
+
+        match mem::replace(&mut s.slots[i], Slot::Free(s.first_free)) {
+            Slot::Live(x) => {
+                s.first_free = index.index;
+                s.size -= 1;
+                Ok(x)
+            }
+            Slot::Free(_) => unreachable!(),
+        }
+    }
+
+    /// Whether the item hasn't been freed.
+    pub fn is_live(&self, index: BrandedIndex<'brand, T>, token: &GhostToken<'brand>) -> bool {
+        self.slots.borrow(token).is_live(index)
+    }
+
+    /// Panics if the item has been freed, even if its slot has been reused
+    /// by a newer item.
+    pub fn get<'a>(
+        &'a self,
+        index: BrandedIndex<'brand, T>,
+        token: &'a GhostToken<'brand>,
+    ) -> &'a T {
+        match self.slots.borrow(token).get(index) {
+            Some(x) => x,
+            None => panic!("Item {} freed!", index.index),
+        }
+    }
+
+    /// Panics if the item has been freed, like `get`.
+    pub fn get_mut<'a>(
+        &'a self,
+        index: BrandedIndex<'brand, T>,
+        token: &'a mut GhostToken<'brand>,
+    ) -> &'a mut T {
+        match self.slots.borrow_mut(token).get_mut(index) {
+            Some(x) => x,
+            None => panic!("Item {} freed!", index.index),
+        }
+    }
+
+    /// Get mutable references to two items at once, or `None` if they're
+    /// the same item. Panics if either has been freed.
+    pub fn get_mut2<'a>(
+        &'a self,
+        a: BrandedIndex<'brand, T>,
+        b: BrandedIndex<'brand, T>,
+        token: &'a mut GhostToken<'brand>,
+    ) -> Option<(&'a mut T, &'a mut T)> {
+        let s = self.slots.borrow_mut(token);
+
+        if !s.is_live(a) || !s.is_live(b) {
+            panic!("Item freed!");
+        }
+
+        match s.slots.get_disjoint_mut([a.index(), b.index()]) {
+            Ok([Slot::Live(x), Slot::Live(y)]) => Some((x, y)),
+            Ok(_) => unreachable!(),
+            Err(_) => None,
+        }
+    }
+
+    pub fn len(&self, token: &GhostToken<'brand>) -> usize {
+        self.slots.borrow(token).size
+    }
+
+    pub fn is_empty(&self, token: &GhostToken<'brand>) -> bool {
+        self.len(token) == 0
+    }
+
+    /// The indexes of the allocated items.
+    pub fn indexes<'a>(
+        &'a self,
+        token: &'a GhostToken<'brand>,
+    ) -> impl Iterator<Item = BrandedIndex<'brand, T>> + 'a {
+        let s = self.slots.borrow(token);
+
+        s.slots
+            .iter()
+            .zip(s.generations.iter())
+            .enumerate()
+            .filter(|(_, (x, _))| matches!(x, Slot::Live(_)))
+            .map(|(i, (_, &g))| BrandedIndex::new(i as u32, g))
+    }
+
+    pub fn iter<'a>(&'a self, token: &'a GhostToken<'brand>) -> impl Iterator<Item = &'a T> + 'a {
+        self.slots
+            .borrow(token)
+            .slots
+            .iter()
+            .filter_map(|x| match x {
+                Slot::Live(x) => Some(x),
+                Slot::Free(_) => None,
+            })
+    }
+
+    pub fn iter_mut<'a>(
+        &'a self,
+        token: &'a mut GhostToken<'brand>,
+    ) -> impl Iterator<Item = &'a mut T> + 'a {
+        self.slots
+            .borrow_mut(token)
+            .slots
+            .iter_mut()
+            .filter_map(|x| match x {
+                Slot::Live(x) => Some(x),
+                Slot::Free(_) => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_index_after_reuse() {
+        PoolBrand::new(|brand, mut token| {
+            let pool = GhostPool::new(brand);
+            let a = pool.alloc(&mut token, "a");
+            let b = pool.alloc(&mut token, "b");
+            assert_eq!(pool.free(&mut token, a), Ok("a"));
+
+            let c = pool.alloc(&mut token, "c");
+            assert_eq!(c.index(), a.index());
+            assert_ne!(c, a);
+
+            assert!(!pool.is_live(a, &token));
+            assert!(pool.is_live(c, &token));
+            assert_eq!(*pool.get(c, &token), "c");
+            assert_eq!(pool.get_mut2(b, c, &mut token), Some((&mut "b", &mut "c")));
+            assert_eq!(pool.indexes(&token).collect::<Vec<_>>(), [c, b]);
+
+            assert_eq!(
+                pool.free(&mut token, a),
+                Err(PoolError::DoubleFree { index: a.index() })
+            );
+
+            assert_eq!(pool.len(&token), 2);
+        })
+    }
+
+    #[test]
+    #[should_panic(expected = "Item 0 freed!")]
+    fn get_stale_index() {
+        PoolBrand::new(|brand, mut token| {
+            let pool = GhostPool::new(brand);
+            let a = pool.alloc(&mut token, 1);
+            pool.free(&mut token, a).unwrap();
+            pool.alloc(&mut token, 2);
+            pool.get(a, &token);
+        })
+    }
+}
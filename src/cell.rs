@@ -1,9 +1,16 @@
-use crate::{cell_pool::CellPool, clear::Clear};
+use crate::{
+    cell_pool::{CellPool, PoolError},
+    clear::Clear,
+    dot::Relations,
+    error::Error,
+    model::{Model, ModelBackend, Scenario},
+};
 
-use super::ref_set::RefSet;
+use super::{ref_set::RefSet, relation::SymmetricRelation};
 use std::cell::{Cell, RefCell};
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Clear)]
+#[clear(before = "Self::unlink_friends")]
 struct Player<'t> {
     game: Cell<Option<GameRef<'t>>>,
     name: RefCell<String>,
@@ -12,6 +19,8 @@ struct Player<'t> {
 }
 
 impl<'t> Player<'t> {
+    const FRIENDS: SymmetricRelation<'t, Self> = SymmetricRelation::new(|p| &p.friends);
+
     fn init(&self, game: GameRef<'t>, name: &str, health: i32) {
         self.game.set(Some(game));
         *self.name.borrow_mut() = name.to_owned();
@@ -19,14 +28,23 @@ impl<'t> Player<'t> {
     }
 
     fn make_friends(&'t self, player2: PlayerRef<'t>) {
-        self.friends.add(player2);
-        player2.friends.add(self);
+        Self::FRIENDS.link(self, player2);
+    }
+
+    /// Run before clearing, so a freed player isn't left behind in the friend
+    /// lists of other players.
+    fn unlink_friends(&self) {
+        Self::FRIENDS.unlink_all(self);
     }
 }
 
-impl<'t> Clear for Player<'t> {
-    fn clear(&self) {
-        self.name.borrow_mut().clear();
+impl<'t> Relations<'t> for Player<'t> {
+    fn relations(&self, f: &mut dyn FnMut(&str, &RefSet<'t, Self>)) {
+        f("friend", &self.friends)
+    }
+
+    fn is_symmetric(name: &str) -> bool {
+        name == "friend"
     }
 }
 
@@ -37,36 +55,211 @@ struct Game<'t> {
 }
 
 impl<'t> Game<'t> {
-    fn new(max_player_count: usize) -> Self {
+    fn new(chunk_size: usize) -> Self {
         Self {
-            players: CellPool::new(max_player_count),
+            players: CellPool::growable(chunk_size),
         }
     }
 
-    fn create_player(&'t self, name: &str, health: i32) -> Result<PlayerRef<'t>, &'static str> {
+    fn create_player(&'t self, name: &str, health: i32) -> Result<PlayerRef<'t>, PoolError> {
         let p = self.players.alloc()?;
         p.init(self, name, health);
         Ok(p)
     }
+
+    /// Frees the player, removing it from the friend lists of other players.
+    fn remove_player(&self, player: PlayerRef<'t>) -> Result<(), PoolError> {
+        self.players.free(player)
+    }
 }
 
 type GameRef<'t> = &'t Game<'t>;
 
-pub fn run_game() -> Result<(), &'static str> {
-    let game = Game::new(100);
+pub struct CellPoolBackend;
+
+struct CellPoolModel<'t> {
+    game: GameRef<'t>,
+}
+
+impl<'t> Model for CellPoolModel<'t> {
+    type Player = PlayerRef<'t>;
+
+    fn create_player(&mut self, name: &str, health: i32) -> Result<PlayerRef<'t>, Error> {
+        Ok(self.game.create_player(name, health)?)
+    }
+
+    fn make_friends(
+        &mut self,
+        player1: &PlayerRef<'t>,
+        player2: &PlayerRef<'t>,
+    ) -> Result<(), Error> {
+        player1.make_friends(player2);
+        Ok(())
+    }
+
+    fn with_name<R>(&self, player: &PlayerRef<'t>, f: impl FnOnce(&str) -> R) -> R {
+        f(&player.name.borrow())
+    }
+
+    fn health(&self, player: &PlayerRef<'t>) -> i32 {
+        player.health.get()
+    }
+
+    fn set_health(&mut self, player: &PlayerRef<'t>, health: i32) {
+        player.health.set(health)
+    }
+
+    fn for_each_friend(&self, player: &PlayerRef<'t>, mut f: impl FnMut(&PlayerRef<'t>)) {
+        for x in player.friends.iter() {
+            f(&x)
+        }
+    }
+
+    fn for_each_player(&self, mut f: impl FnMut(&PlayerRef<'t>)) {
+        for x in self.game.players.iter() {
+            f(&x)
+        }
+    }
+
+    fn player_id(&self, player: &PlayerRef<'t>) -> usize {
+        *player as *const Player as usize
+    }
+
+    fn remove_player(&mut self, player: &PlayerRef<'t>) -> Result<(), Error> {
+        Ok(self.game.remove_player(player)?)
+    }
+}
 
-    let p1 = game.create_player("Eric", 10)?;
-    let p2 = game.create_player("Tom", 15)?;
-    let p3 = game.create_player("Carl", 17)?;
+impl ModelBackend for CellPoolBackend {
+    const NAME: &'static str = "Cell pool";
+
+    fn run<S: Scenario>(scenario: S) -> S::Output {
+        let game = Game::new(100);
+        scenario.run(&mut CellPoolModel { game: &game })
+    }
+}
 
-    p1.make_friends(p2);
-    p1.make_friends(p3);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persist::{PersistError, PlayerData, PoolData};
 
-    p2.health.set(20);
+    fn save(game: &Game<'_>) -> PoolData<PlayerData> {
+        PoolData::save(&game.players, |p| PlayerData {
+            name: p.name.borrow().clone(),
+            health: p.health.get(),
+        })
+    }
 
-    for x in p1.friends.iter() {
-        println!("{}: {}", x.name.borrow(), x.health.get())
+    fn load<'t>(
+        game: &'t Game<'t>,
+        data: &PoolData<PlayerData>,
+    ) -> Result<Vec<PlayerRef<'t>>, Error> {
+        data.load(&game.players, |p, x| p.init(game, &x.name, x.health))
     }
 
-    Ok(())
+    fn friend_names(player: PlayerRef<'_>) -> Vec<String> {
+        let mut names: Vec<_> = player
+            .friends
+            .iter()
+            .map(|x| x.name.borrow().clone())
+            .collect();
+
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn free_unlinks_friends() {
+        let game = Game::new(4);
+        let eric = game.create_player("Eric", 10).unwrap();
+        let tom = game.create_player("Tom", 15).unwrap();
+        let carl = game.create_player("Carl", 17).unwrap();
+        eric.make_friends(tom);
+        carl.make_friends(tom);
+        tom.make_friends(tom);
+
+        game.players.free(tom).unwrap();
+
+        assert!(eric.friends.is_empty());
+        assert!(carl.friends.is_empty());
+        assert!(tom.friends.is_empty());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let game = Game::new(2);
+        let eric = game.create_player("Eric", 10).unwrap();
+        let tom = game.create_player("Tom", 15).unwrap();
+        let carl = game.create_player("Carl", 17).unwrap();
+        let anna = game.create_player("Anna", 12).unwrap();
+        eric.make_friends(tom);
+        eric.make_friends(carl);
+        carl.make_friends(carl);
+        anna.make_friends(eric);
+        game.remove_player(tom).unwrap();
+
+        let data = save(&game);
+        let indexes: Vec<_> = data.items.iter().map(|x| x.index).collect();
+        assert_eq!(indexes, [0, 2, 3]);
+
+        let json = serde_json::to_string(&data).unwrap();
+        let data: PoolData<PlayerData> = serde_json::from_str(&json).unwrap();
+
+        let game2 = Game::new(2);
+        let players = load(&game2, &data).unwrap();
+        let names: Vec<_> = players.iter().map(|x| x.name.borrow().clone()).collect();
+        assert_eq!(names, ["Eric", "Carl", "Anna"]);
+        assert_eq!(players[1].health.get(), 17);
+        assert_eq!(friend_names(players[0]), ["Anna", "Carl"]);
+        assert_eq!(friend_names(players[1]), ["Carl", "Eric"]);
+        assert_eq!(friend_names(players[2]), ["Eric"]);
+        assert!(std::ptr::eq(players[0].game.get().unwrap(), &game2));
+    }
+
+    #[test]
+    fn load_invalid_index() {
+        let game = Game::new(2);
+        let eric = game.create_player("Eric", 10).unwrap();
+        let tom = game.create_player("Tom", 15).unwrap();
+        eric.make_friends(tom);
+
+        let mut data = save(&game);
+        data.items[1].index = 5;
+
+        let game2 = Game::new(2);
+
+        assert_eq!(
+            load(&game2, &data).err(),
+            Some(PersistError::InvalidIndex { index: 1 }.into())
+        );
+
+        assert!(game2.players.is_empty());
+    }
+
+    #[test]
+    fn load_asymmetric_friendship() {
+        let game = Game::new(2);
+        let eric = game.create_player("Eric", 10).unwrap();
+        let tom = game.create_player("Tom", 15).unwrap();
+        eric.make_friends(tom);
+
+        let mut data = save(&game);
+        data.items[1].relations[0].1.clear();
+
+        let game2 = Game::new(2);
+
+        assert_eq!(
+            load(&game2, &data).err(),
+            Some(
+                PersistError::AsymmetricRelation {
+                    index: 0,
+                    target: 1
+                }
+                .into()
+            )
+        );
+
+        assert!(game2.players.is_empty());
+    }
 }
@@ -1,12 +1,123 @@
-use crate::{clear::Clear, utils::decr};
-use std::cell::Cell;
+use crate::{
+    clear::Clear,
+    dot::{self, Relations},
+    ghost_cell::{GhostCell, GhostToken},
+    utils::decr,
+};
+use rayon::{iter::plumbing::UnindexedConsumer, prelude::*};
+use std::{
+    cell::{Cell, UnsafeCell},
+    collections::HashMap,
+    error::Error,
+    fmt,
+    hash::{Hash, Hasher},
+    iter,
+    marker::PhantomData,
+    mem,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolError {
+    /// A fixed size pool has no free slots left.
+    Exhausted { capacity: Index },
+    /// The item wasn't allocated from this pool.
+    ForeignItem,
+    /// The item at `index` has already been freed.
+    DoubleFree { index: Index },
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::Exhausted { capacity } => {
+                write!(f, "Pool exhausted at capacity {}!", capacity)
+            }
+            PoolError::ForeignItem => write!(f, "Item doesn't belong to this pool!"),
+            PoolError::DoubleFree { index } => write!(f, "Item {} already freed!", index),
+        }
+    }
+}
+
+impl Error for PoolError {}
 
 type Index = usize;
+type Generation = u32;
 
-pub struct CellPool<T> {
+/// Marks the end of the item and free lists.
+const NONE: Index = Index::MAX;
+
+/// A slot's generation is odd while it's allocated and even while it's free,
+/// so it changes both when the slot is freed and when it's reused.
+fn is_live(generation: Generation) -> bool {
+    generation & 1 == 1
+}
+
+/// A slot generation. It's only ever updated by the thread owning the pool,
+/// but is atomic so `par_iter` can check which slots are allocated from other
+/// threads.
+struct GenerationCell(AtomicU32);
+
+impl GenerationCell {
+    fn new(generation: Generation) -> Self {
+        Self(AtomicU32::new(generation))
+    }
+
+    fn get(&self) -> Generation {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, generation: Generation) {
+        self.0.store(generation, Ordering::Relaxed)
+    }
+
+    fn is_live(&self) -> bool {
+        is_live(self.get())
+    }
+}
+
+struct Chunk<T> {
     items: Vec<T>,
     prev: Vec<Cell<Index>>,
     next: Vec<Cell<Index>>,
+    generation: Vec<GenerationCell>,
+}
+
+impl<T: Default> Chunk<T> {
+    /// Creates a chunk whose slots are linked into a free list of the indexes
+    /// `base..base + size`.
+    fn new(base: Index, size: Index) -> Self {
+        let mut c = Self {
+            items: Vec::with_capacity(size),
+            prev: Vec::with_capacity(size),
+            next: Vec::with_capacity(size),
+            generation: Vec::with_capacity(size),
+        };
+
+        for i in base..base + size {
+            c.items.push(Default::default());
+            c.prev.push(Cell::new(NONE));
+            c.next
+                .push(Cell::new(if i + 1 < base + size { i + 1 } else { NONE }));
+            c.generation.push(GenerationCell::new(0));
+        }
+
+        c
+    }
+}
+
+/// A pool of items linked into an intrusive list, allocated and freed through
+/// shared references.
+///
+/// Items are stored in fixed-size chunks which are never moved or reallocated,
+/// so references returned by `alloc` stay valid when a growable pool appends
+/// a new chunk.
+pub struct CellPool<T> {
+    /// Only ever appended to, and never while a reference into the `Vec`
+    /// itself (as opposed to into a chunk) is alive.
+    chunks: UnsafeCell<Vec<Chunk<T>>>,
+    chunk_size: Index,
+    growable: bool,
     first_free: Cell<Index>,
     first: Cell<Index>,
     last: Cell<Index>,
@@ -14,123 +125,439 @@ pub struct CellPool<T> {
 }
 
 impl<T: Default> CellPool<T> {
+    /// Creates a pool that holds at most `capacity` items.
     pub fn new(capacity: Index) -> Self {
-        let mut s = Self {
-            items: Vec::with_capacity(capacity),
-            prev: Vec::with_capacity(capacity),
-            next: Vec::with_capacity(capacity),
-            first_free: Cell::new(0),
-            first: Cell::new(0),
-            last: Cell::new(0),
-            size: Cell::new(0),
+        Self::with_chunks(capacity, false)
+    }
+
+    /// Creates a pool that allocates room for `chunk_size` more items each
+    /// time it runs full.
+    pub fn growable(chunk_size: Index) -> Self {
+        assert!(chunk_size > 0, "Chunk size must be positive!");
+        Self::with_chunks(chunk_size, true)
+    }
+
+    fn with_chunks(chunk_size: Index, growable: bool) -> Self {
+        let chunks = if chunk_size > 0 {
+            vec![Chunk::new(0, chunk_size)]
+        } else {
+            Vec::new()
         };
 
-        for i in 0..capacity {
-            s.items.push(Default::default());
-            s.prev.push(Cell::new(capacity));
-            s.next.push(Cell::new(i + 1));
+        Self {
+            chunks: UnsafeCell::new(chunks),
+            chunk_size,
+            growable,
+            first_free: Cell::new(if chunk_size > 0 { 0 } else { NONE }),
+            first: Cell::new(NONE),
+            last: Cell::new(NONE),
+            size: Cell::new(0),
         }
-
-        s
     }
-}
 
-impl<T> CellPool<T> {
-    pub fn alloc(&self) -> Result<&T, &'static str> {
+    pub fn alloc(&self) -> Result<&T, PoolError> {
         let s = self.size.get();
 
-        if s >= self.items.capacity() {
-            return Err("Pool empty!");
+        if s >= self.capacity() {
+            if !self.growable {
+                return Err(PoolError::Exhausted {
+                    capacity: self.capacity(),
+                });
+            }
+
+            self.grow();
         }
 
         // Remove first from free list
         let index = self.first_free.get();
-        self.first_free.set(self.next[index].get());
+        self.first_free.set(self.next(index).get());
 
         // Add last in item list
         let li = self.last.get();
-        self.next[li].set(index);
-        self.prev[index].set(li);
-        self.next[index].set(self.items.capacity());
-        self.last.set(index);
 
-        if s == 0 {
+        if li == NONE {
             self.first.set(index)
+        } else {
+            self.next(li).set(index);
         }
 
+        self.prev(index).set(li);
+        self.next(index).set(NONE);
+        self.last.set(index);
+
+        let g = self.generation(index);
+        g.set(g.get().wrapping_add(1));
+
         self.size.set(s + 1);
-        Ok(&self.items[index])
+        Ok(self.item(index))
+    }
+
+    /// Appends a chunk and puts its slots first in the free list.
+    fn grow(&self) {
+        let base = self.capacity();
+        let chunk = Chunk::new(base, self.chunk_size);
+        chunk.next[self.chunk_size - 1].set(self.first_free.get());
+
+        // Moves the chunk list, so no reference into it may be held across
+        // code that can allocate, e.g. a `Clear` impl. References into the
+        // chunks themselves stay valid.
+        unsafe { (*self.chunks.get()).push(chunk) };
+        self.first_free.set(base);
+    }
+}
+
+impl<T> CellPool<T> {
+    pub fn len(&self) -> Index {
+        self.size.get()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of items the pool has room for without growing.
+    pub fn capacity(&self) -> Index {
+        self.chunks().len() * self.chunk_size
+    }
+
+    /// Returns a handle to `p`, or `None` if `p` isn't an allocated item in
+    /// this pool.
+    pub fn handle(&self, p: &T) -> Option<Handle<T>> {
+        let index = self.index_of(p)?;
+        let generation = self.generation(index).get();
+
+        if is_live(generation) {
+            Some(Handle::new(index, generation))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the item `handle` was created for, or `None` if it has been
+    /// freed since (even if its slot has been reused by a newer item).
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        if handle.index < self.capacity()
+            && self.generation(handle.index).get() == handle.generation
+        {
+            Some(self.item(handle.index))
+        } else {
+            None
+        }
     }
 
-    pub fn iter(&self) -> PoolIter<T> {
+    pub fn iter(&self) -> PoolIter<'_, T> {
         PoolIter {
             pool: self,
             index: self.first.get(),
         }
     }
-}
 
-impl<T: Clear> CellPool<T> {
-    pub fn free(&self, p: &T) -> Result<(), &'static str> {
-        let si = unsafe { (p as *const T).offset_from(&self.items[0] as *const T) };
+    /// Like `iter`, but also yields the slot index of each item.
+    pub fn iter_indexed(&self) -> impl Iterator<Item = (Index, &T)> + '_ {
+        let mut index = self.first.get();
 
-        if si < 0 {
-            return Err("Invalid item!");
-        }
+        iter::from_fn(move || {
+            let i = index;
+
+            if i == NONE {
+                None
+            } else {
+                index = self.next(i).get();
+                Some((i, self.item(i)))
+            }
+        })
+    }
+
+    /// Maps the address of each allocated item to its slot index, so many
+    /// items can be looked up without searching the chunks for each one.
+    pub(crate) fn slot_indexes(&self) -> HashMap<usize, Index> {
+        self.iter_indexed()
+            .map(|(i, x)| (x as *const T as usize, i))
+            .collect()
+    }
 
-        let i = si as usize;
-        let p = self.prev[i].get();
+    /// Frees `p` after resetting it with `clear`, for items that can't
+    /// implement `Clear`, e.g. because they need a token to be accessed.
+    pub fn free_with(&self, p: &T, clear: impl FnOnce(&T)) -> Result<(), PoolError> {
+        let i = self.index_of(p).ok_or(PoolError::ForeignItem)?;
+        let g = self.generation(i);
 
-        if p >= self.items.capacity() {
-            return Err("Item already freed!");
+        if !is_live(g.get()) {
+            return Err(PoolError::DoubleFree { index: i });
         }
 
-        let n = self.next[i].get();
+        // Reset the item so it's ready for reuse, and so it can release any
+        // references to and from it
+        clear(self.item(i));
 
-        // Remove from item list
-        self.next[p].set(n);
-        self.prev[n].set(p);
+        let p = self.prev(i).get();
+        let n = self.next(i).get();
 
-        if i == self.first.get() {
+        // Remove from item list
+        if p == NONE {
             self.first.set(n)
+        } else {
+            self.next(p).set(n)
         }
 
-        if i == self.last.get() {
+        if n == NONE {
             self.last.set(p)
+        } else {
+            self.prev(n).set(p)
         }
 
         // Add to free list
         let ff = self.first_free.get();
         self.first_free.set(i);
-        self.prev[i].set(self.items.capacity());
-        self.next[i].set(ff);
+        self.prev(i).set(NONE);
+        self.next(i).set(ff);
+        g.set(g.get().wrapping_add(1));
 
         decr(&self.size);
         Ok(())
     }
+
+    fn chunks(&self) -> &[Chunk<T>] {
+        unsafe { &*self.chunks.get() }
+    }
+
+    fn chunk(&self, i: Index) -> (&Chunk<T>, Index) {
+        (&self.chunks()[i / self.chunk_size], i % self.chunk_size)
+    }
+
+    fn item(&self, i: Index) -> &T {
+        let (c, j) = self.chunk(i);
+        &c.items[j]
+    }
+
+    fn prev(&self, i: Index) -> &Cell<Index> {
+        let (c, j) = self.chunk(i);
+        &c.prev[j]
+    }
+
+    fn next(&self, i: Index) -> &Cell<Index> {
+        let (c, j) = self.chunk(i);
+        &c.next[j]
+    }
+
+    fn generation(&self, i: Index) -> &GenerationCell {
+        let (c, j) = self.chunk(i);
+        &c.generation[j]
+    }
+
+    /// Returns the index of `p` if it points at an item in one of the pool's
+    /// chunks.
+    fn index_of(&self, p: &T) -> Option<Index> {
+        self.chunks()
+            .iter()
+            .enumerate()
+            .find_map(|(ci, c)| Some(ci * self.chunk_size + slot_index(&c.items, p)?))
+    }
+}
+
+/// Returns the index of `p` in `items` if it points at the start of one of
+/// them.
+///
+/// Only plain addresses are compared, as `offset_from` is undefined for
+/// pointers into different allocations, e.g. an item from another pool.
+pub(crate) fn slot_index<T>(items: &[T], p: &T) -> Option<Index> {
+    let addr = p as *const T as usize;
+    let size = mem::size_of::<T>().max(1);
+    let offset = addr.checked_sub(items.as_ptr() as usize)?;
+
+    if offset < items.len() * size && offset % size == 0 {
+        Some(offset / size)
+    } else {
+        None
+    }
+}
+
+impl<T> CellPool<T> {
+    /// Exports the allocated items as a directed graph with an edge per
+    /// reference, named after its relation. Each item is labelled with
+    /// `label` and identified by its slot index.
+    pub fn to_dot<'t>(&self, label: impl Fn(&T) -> String) -> String
+    where
+        T: Relations<'t>,
+    {
+        let indexes = self.slot_indexes();
+        let mut out = String::from("digraph {\n");
+
+        for (i, x) in self.iter_indexed() {
+            dot::write_node(&mut out, i, &label(x));
+        }
+
+        for (from, x) in self.iter_indexed() {
+            x.relations(&mut |name, targets| {
+                for y in targets.iter() {
+                    if let Some(&to) = indexes.get(&(y as *const T as usize)) {
+                        dot::write_edge(&mut out, from, "->", to, name);
+                    }
+                }
+            });
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl<T: Clear> CellPool<T> {
+    pub fn free(&self, p: &T) -> Result<(), PoolError> {
+        self.free_with(p, T::clear)
+    }
 }
 
+/// Items allocated by a `clear` callback are freed along with the others, but
+/// aren't cleared themselves.
 impl<T: Clear> Clear for CellPool<T> {
     fn clear(&self) {
-        for v in self.items.iter() {
-            v.clear()
+        // Walk the slots by index, as a callback allocating from the pool may
+        // grow it and move the chunk list
+        for i in 0..self.capacity() {
+            self.item(i).clear()
         }
 
-        for x in self.prev.iter() {
-            x.set(self.items.capacity())
-        }
+        let capacity = self.capacity();
+
+        for i in 0..capacity {
+            self.prev(i).set(NONE);
+            self.next(i)
+                .set(if i + 1 < capacity { i + 1 } else { NONE });
+
+            // Invalidate all handles to the cleared items
+            let g = self.generation(i);
 
-        for (i, x) in self.next.iter().enumerate() {
-            x.set(i + 1)
+            if g.is_live() {
+                g.set(g.get().wrapping_add(1))
+            }
         }
 
-        self.first_free.set(0);
-        self.first.set(0);
-        self.last.set(0);
+        self.first_free.set(if capacity > 0 { 0 } else { NONE });
+        self.first.set(NONE);
+        self.last.set(NONE);
         self.size.set(0);
     }
 }
 
+/// A reference to a pool item that, unlike `&T`, can detect that the item has
+/// been freed and its slot reused.
+///
+/// Handles are meant for references that can outlive the item unnoticed, e.g.
+/// ones kept outside the pool. Items referring to each other, like the
+/// players' friend lists in `cell`, still hold `&T`s, and must instead be
+/// removed from each other when freed so no reference to a reused slot is
+/// left behind.
+pub struct Handle<T> {
+    index: Index,
+    generation: Generation,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    fn new(index: Index, generation: Generation) -> Self {
+        Self {
+            index,
+            generation,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn index(self) -> Index {
+        self.index
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({}v{})", self.index, self.generation)
+    }
+}
+
+impl<T: Sync> CellPool<T> {
+    /// Returns a parallel iterator over the allocated items. Unlike `iter`,
+    /// which follows the item list, it scans all slots so the work can be
+    /// split between threads, and doesn't yield the items in allocation
+    /// order.
+    pub fn par_iter(&self) -> ParPoolIter<'_, T> {
+        ParPoolIter {
+            chunks: self
+                .chunks()
+                .iter()
+                .map(|c| (&c.items[..], &c.generation[..]))
+                .collect(),
+        }
+    }
+}
+
+impl<'brand, T: Send + Sync> CellPool<GhostCell<'brand, T>> {
+    /// Returns a parallel iterator over the values of the allocated cells,
+    /// borrowed through `token`.
+    pub fn par_iter_borrow<'a>(
+        &'a self,
+        token: &'a GhostToken<'brand>,
+    ) -> impl ParallelIterator<Item = &'a T> + use<'a, 'brand, T> {
+        self.par_iter().map(move |x| x.borrow(token))
+    }
+
+    /// Returns a parallel iterator over mutable references to the values of
+    /// the allocated cells, borrowed through `token`.
+    pub fn par_iter_mut<'a>(
+        &'a self,
+        _token: &'a mut GhostToken<'brand>,
+    ) -> impl ParallelIterator<Item = &'a mut T> + use<'a, 'brand, T> {
+        // Safe as the token is borrowed mutably for `'a`, like in
+        // `GhostCell::borrow_mut`, and every cell is visited only once
+        self.par_iter().map(|x| unsafe { &mut *x.as_ptr() })
+    }
+}
+
+pub struct ParPoolIter<'t, T> {
+    chunks: Vec<(&'t [T], &'t [GenerationCell])>,
+}
+
+impl<'t, T: Sync> ParallelIterator for ParPoolIter<'t, T> {
+    type Item = &'t T;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.chunks
+            .into_par_iter()
+            .flat_map(|(items, generation)| {
+                items
+                    .par_iter()
+                    .zip(generation.par_iter())
+                    .filter_map(|(x, g)| if g.is_live() { Some(x) } else { None })
+            })
+            .drive_unindexed(consumer)
+    }
+}
+
 pub struct PoolIter<'t, T> {
     pool: &'t CellPool<T>,
     index: usize,
@@ -142,11 +569,128 @@ impl<'t, T> Iterator for PoolIter<'t, T> {
     fn next(&mut self) -> Option<Self::Item> {
         let i = self.index;
 
-        if i >= self.pool.items.capacity() {
+        if i == NONE {
             None
         } else {
-            self.index = self.pool.next[i].get();
-            Some(&self.pool.items[i])
+            self.index = self.pool.next(i).get();
+            Some(self.pool.item(i))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_foreign_item() {
+        let pool1 = CellPool::<Cell<i32>>::new(4);
+        let pool2 = CellPool::<Cell<i32>>::new(4);
+        let x = pool2.alloc().unwrap();
+
+        assert_eq!(pool1.free(x), Err(PoolError::ForeignItem));
+        assert_eq!(pool1.free(&Cell::new(0)), Err(PoolError::ForeignItem));
+        assert_eq!(pool2.len(), 1);
+    }
+
+    #[test]
+    fn double_free() {
+        let pool = CellPool::<Cell<i32>>::new(4);
+        let x = pool.alloc().unwrap();
+        pool.alloc().unwrap();
+
+        assert_eq!(pool.free(x), Ok(()));
+        assert_eq!(pool.free(x), Err(PoolError::DoubleFree { index: 0 }));
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn stale_handle() {
+        let pool = CellPool::<Cell<i32>>::new(4);
+        let x = pool.alloc().unwrap();
+        let hx = pool.handle(x).unwrap();
+        assert!(std::ptr::eq(pool.get(hx).unwrap(), x));
+
+        pool.free(x).unwrap();
+        assert!(pool.get(hx).is_none());
+
+        // The slot is reused, but the old handle still doesn't reach it
+        let y = pool.alloc().unwrap();
+        let hy = pool.handle(y).unwrap();
+        assert!(std::ptr::eq(x, y));
+        assert!(pool.get(hx).is_none());
+        assert!(pool.get(hy).is_some());
+
+        pool.clear();
+        assert!(pool.get(hy).is_none());
+        assert!(pool.handle(y).is_none());
+    }
+
+    /// Allocates from its pool when cleared.
+    #[derive(Default)]
+    struct Grower<'p> {
+        pool: Cell<Option<&'p CellPool<Grower<'p>>>>,
+    }
+
+    impl Clear for Grower<'_> {
+        fn clear(&self) {
+            if let Some(pool) = self.pool.take() {
+                for _ in 0..64 {
+                    pool.alloc().unwrap();
+                }
+            }
         }
     }
+
+    #[test]
+    fn clear_allocating_items() {
+        let pool = CellPool::<Grower>::growable(1);
+        pool.alloc().unwrap().pool.set(Some(&pool));
+        pool.clear();
+
+        assert!(pool.capacity() >= 65);
+        assert_eq!(pool.len(), 0);
+        assert_eq!(pool.iter().count(), 0);
+        assert_eq!(pool.first_free.get(), 0);
+    }
+
+    #[test]
+    fn free_in_grown_pool() {
+        let pool1 = CellPool::<Cell<i32>>::growable(2);
+        let pool2 = CellPool::<Cell<i32>>::growable(2);
+        let xs: Vec<_> = (0..5).map(|_| pool1.alloc().unwrap()).collect();
+        let ys: Vec<_> = (0..5).map(|_| pool2.alloc().unwrap()).collect();
+
+        assert_eq!(pool1.capacity(), 6);
+
+        for &y in ys.iter() {
+            assert_eq!(pool1.free(y), Err(PoolError::ForeignItem));
+        }
+
+        assert_eq!(pool1.free(xs[3]), Ok(()));
+        assert_eq!(pool1.free(xs[3]), Err(PoolError::DoubleFree { index: 3 }));
+        assert_eq!(pool1.free(xs[4]), Ok(()));
+        assert_eq!(pool1.free(xs[4]), Err(PoolError::DoubleFree { index: 4 }));
+        assert_eq!(pool1.len(), 3);
+        assert_eq!(pool2.len(), 5);
+    }
+
+    #[test]
+    fn par_iter_mut() {
+        GhostToken::new(|mut token| {
+            let pool = CellPool::<GhostCell<i32>>::growable(3);
+            let xs: Vec<_> = (0..10).map(|_| pool.alloc().unwrap()).collect();
+
+            for (i, x) in xs.iter().enumerate() {
+                *x.borrow_mut(&mut token) = i as i32;
+            }
+
+            pool.free_with(xs[4], |x| *x.borrow_mut(&mut token) = 0)
+                .unwrap();
+            pool.par_iter_mut(&mut token).for_each(|x| *x += 100);
+
+            let values: Vec<_> = xs.iter().map(|x| *x.borrow(&token)).collect();
+            assert_eq!(values, [100, 101, 102, 103, 0, 105, 106, 107, 108, 109]);
+        })
+    }
 }
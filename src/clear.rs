@@ -1,11 +1,85 @@
-use std::cell::Cell;
+use crate::ghost_cell::{GhostCell, GhostToken};
+use std::cell::{Cell, RefCell};
 
+pub use clear_derive::{Clear, ClearMut};
+
+/// Resets a value through a shared reference, e.g. before it's reused by a
+/// pool.
 pub trait Clear {
     fn clear(&self);
 }
 
+/// Resets a value through a mutable reference. Implemented for everything
+/// that implements `Clear`.
+pub trait ClearMut {
+    fn clear_mut(&mut self);
+}
+
+/// Resets a value that can only be accessed through a token.
+pub trait ClearWith<Token> {
+    fn clear_with(&self, token: &mut Token);
+}
+
+impl<T: Clear + ?Sized> ClearMut for T {
+    fn clear_mut(&mut self) {
+        self.clear()
+    }
+}
+
 impl<T: Default> Clear for Cell<T> {
     fn clear(&self) {
         self.set(Default::default())
     }
 }
+
+impl<T: Default> Clear for RefCell<T> {
+    fn clear(&self) {
+        *self.borrow_mut() = Default::default()
+    }
+}
+
+impl<T> ClearMut for Option<T> {
+    fn clear_mut(&mut self) {
+        *self = None
+    }
+}
+
+impl<T> ClearMut for Vec<T> {
+    fn clear_mut(&mut self) {
+        self.clear()
+    }
+}
+
+impl ClearMut for String {
+    fn clear_mut(&mut self) {
+        self.clear()
+    }
+}
+
+macro_rules! clear_mut_default {
+    ($($t:ty),*) => {
+        $(
+            impl ClearMut for $t {
+                fn clear_mut(&mut self) {
+                    *self = Default::default()
+                }
+            }
+        )*
+    };
+}
+
+clear_mut_default!(
+    bool, char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64
+);
+
+impl<'brand, T: ClearMut> ClearMut for GhostCell<'brand, T> {
+    fn clear_mut(&mut self) {
+        self.get_mut().clear_mut()
+    }
+}
+
+impl<'brand, T: ClearMut> ClearWith<GhostToken<'brand>> for GhostCell<'brand, T> {
+    fn clear_with(&self, token: &mut GhostToken<'brand>) {
+        self.borrow_mut(token).clear_mut()
+    }
+}
@@ -0,0 +1,104 @@
+//! Exports entity graphs in the Graphviz DOT format.
+
+use crate::{
+    model::{self, Model},
+    ref_set::RefSet,
+};
+use std::fmt::Write;
+
+/// Implemented by pool items with references to other items of the same
+/// pool, so the pool can be exported with `CellPool::to_dot` and saved with
+/// `PoolData`.
+pub trait Relations<'t>: Sized + 't {
+    /// Calls `f` with the name and the set of targets of each relation.
+    fn relations(&self, f: &mut dyn FnMut(&str, &RefSet<'t, Self>));
+
+    /// Whether the relation called `name` is symmetric, i.e. kept with a
+    /// `SymmetricRelation`. `PoolData` refuses to load such a relation if an
+    /// item isn't also in the sets of its targets.
+    fn is_symmetric(_name: &str) -> bool {
+        false
+    }
+}
+
+/// Quotes `s` as a DOT string.
+pub(crate) fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+pub(crate) fn write_node(out: &mut String, id: usize, label: &str) {
+    writeln!(out, "    n{} [label={}];", id, quote(label)).unwrap();
+}
+
+pub(crate) fn write_edge(out: &mut String, from: usize, op: &str, to: usize, label: &str) {
+    writeln!(
+        out,
+        "    n{} {} n{} [label={}];",
+        from,
+        op,
+        to,
+        quote(label)
+    )
+    .unwrap();
+}
+
+/// Exports the players of `m` as an undirected graph with an edge per
+/// friendship, labelling each player with `label`.
+pub fn to_dot<M: Model>(m: &M, label: impl Fn(&M, &M::Player) -> String) -> String {
+    let (players, friendships) = model::friendships(m);
+    let mut out = String::from("graph {\n");
+
+    for (i, p) in players.iter().enumerate() {
+        write_node(&mut out, i, &label(m, p));
+    }
+
+    for (i, j) in friendships {
+        write_edge(&mut out, i, "--", j, "friend");
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+/// Labels a player with its name and health.
+pub fn name_and_health<M: Model>(m: &M, player: &M::Player) -> String {
+    m.with_name(player, |name| format!("{}: {}", name, m.health(player)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cell::CellPoolBackend,
+        error::Error,
+        model::{ModelBackend, Scenario},
+    };
+
+    struct Export;
+
+    impl Scenario for Export {
+        type Output = Result<String, Error>;
+
+        fn run<M: Model>(self, m: &mut M) -> Self::Output {
+            let p1 = m.create_player("Eric", 10)?;
+            let p2 = m.create_player("Tom", 15)?;
+            m.make_friends(&p1, &p2)?;
+            m.make_friends(&p2, &p2)?;
+            Ok(to_dot(m, name_and_health))
+        }
+    }
+
+    #[test]
+    fn export_model() {
+        assert_eq!(
+            CellPoolBackend::run(Export).unwrap(),
+            "graph {
+    n0 [label=\"Eric: 10\"];
+    n1 [label=\"Tom: 15\"];
+    n0 -- n1 [label=\"friend\"];
+    n1 -- n1 [label=\"friend\"];
+}
+"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{cell_pool::PoolError, persist::PersistError, ref_set::RefSetError};
+use std::fmt;
+
+/// Any error raised while running one of the models.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Pool(PoolError),
+    RefSet(RefSetError),
+    Persist(PersistError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Pool(e) => e.fmt(f),
+            Error::RefSet(e) => e.fmt(f),
+            Error::Persist(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Pool(e) => Some(e),
+            Error::RefSet(e) => Some(e),
+            Error::Persist(e) => Some(e),
+        }
+    }
+}
+
+impl From<PoolError> for Error {
+    fn from(e: PoolError) -> Self {
+        Error::Pool(e)
+    }
+}
+
+impl From<RefSetError> for Error {
+    fn from(e: RefSetError) -> Self {
+        Error::RefSet(e)
+    }
+}
+
+impl From<PersistError> for Error {
+    fn from(e: PersistError) -> Self {
+        Error::Persist(e)
+    }
+}
+
+impl Error {
+    /// The process exit code to report this error with. Codes below 10 are
+    /// left for the command line runner itself.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Pool(PoolError::Exhausted { .. }) => 10,
+            Error::Pool(PoolError::ForeignItem) => 11,
+            Error::Pool(PoolError::DoubleFree { .. }) => 12,
+            Error::RefSet(RefSetError::NotFound) => 21,
+            Error::Persist(PersistError::InvalidIndex { .. }) => 30,
+            Error::Persist(PersistError::AsymmetricRelation { .. }) => 31,
+        }
+    }
+}
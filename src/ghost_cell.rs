@@ -110,6 +110,7 @@ impl<'id> GhostToken<'id> {
     /// cannot be chosen by the client to replicate an existing `GhostToken`, we
     /// know that `'id` is unique per call of `new`.
     #[inline]
+    #[allow(clippy::new_ret_no_self)]
     pub fn new<F, R>(f: F) -> R
     where
         F: for<'new_id> FnOnce(GhostToken<'new_id>) -> R,
@@ -245,6 +246,111 @@ impl<'id, T> GhostCell<'id, T> {
     }
 }
 
+impl<'id, T> GhostCell<'id, T> {
+    /// Get mutable references to the items of two cells at once, or `None` if
+    /// the cells overlap.
+    #[inline]
+    pub fn borrow_mut2<'a, U>(
+        &'a self,
+        other: &'a GhostCell<'id, U>,
+        _token: &'a mut GhostToken<'id>,
+    ) -> Option<(&'a mut T, &'a mut U)> {
+        if !disjoint(self, other) {
+            return None;
+        }
+
+        unsafe {
+            // The token is borrowed mutably at `'a`, just like in `borrow_mut`,
+            // and since the cells don't overlap the two mutable references
+            // can't alias each other.
+            Some((&mut *self.value.get(), &mut *other.value.get()))
+        }
+    }
+
+    /// Get mutable references to the items of three cells at once, or `None`
+    /// if any two of the cells overlap.
+    #[inline]
+    pub fn borrow_mut3<'a, U, V>(
+        &'a self,
+        second: &'a GhostCell<'id, U>,
+        third: &'a GhostCell<'id, V>,
+        _token: &'a mut GhostToken<'id>,
+    ) -> Option<(&'a mut T, &'a mut U, &'a mut V)> {
+        if !disjoint(self, second) || !disjoint(self, third) || !disjoint(second, third) {
+            return None;
+        }
+
+        unsafe {
+            // See `borrow_mut2`.
+            Some((
+                &mut *self.value.get(),
+                &mut *second.value.get(),
+                &mut *third.value.get(),
+            ))
+        }
+    }
+
+    /// Get mutable references to the items of any number of cells at once, or
+    /// `None` if any two of the cells overlap.
+    #[inline]
+    pub fn borrow_mut_array<'a, const N: usize>(
+        cells: [&'a Self; N],
+        _token: &'a mut GhostToken<'id>,
+    ) -> Option<[&'a mut T; N]> {
+        for (i, a) in cells.iter().enumerate() {
+            if cells[i + 1..].iter().any(|b| !disjoint(*a, *b)) {
+                return None;
+            }
+        }
+
+        unsafe {
+            // See `borrow_mut2`.
+            Some(cells.map(|c| &mut *c.value.get()))
+        }
+    }
+}
+
+impl<'id, T> GhostCell<'id, T> {
+    /// Get an immutable reference to the items of a slice of cells, for as
+    /// long as the owning token is immutably borrowed.
+    #[inline]
+    pub fn borrow_slice<'a>(cells: &'a [Self], _token: &'a GhostToken<'id>) -> &'a [T] {
+        unsafe {
+            // See `borrow`. `GhostCell<T>` and `UnsafeCell<T>` have the same
+            // layout as `T`, so the slices have the same layout too.
+            &*(cells as *const [Self] as *const [T])
+        }
+    }
+
+    /// Get a mutable reference to the items of a slice of cells, for as long
+    /// as the owning token is mutably borrowed.
+    ///
+    /// The slice can then be split into disjoint parts, e.g. with rayon's
+    /// `par_chunks_mut`, and mutated from several threads at once.
+    #[inline]
+    pub fn borrow_slice_mut<'a>(cells: &'a [Self], _token: &'a mut GhostToken<'id>) -> &'a mut [T] {
+        unsafe {
+            // See `borrow_mut` and `borrow_slice`. The cells in a slice never
+            // overlap, so neither do the items.
+            let first = UnsafeCell::raw_get(cells.as_ptr() as *const UnsafeCell<T>);
+            core::slice::from_raw_parts_mut(first, cells.len())
+        }
+    }
+}
+
+/// Returns true if the values of the two cells occupy separate memory. Cells
+/// at the same address are never disjoint, even if their values are zero
+/// sized.
+#[inline]
+fn disjoint<A, B>(a: &GhostCell<'_, A>, b: &GhostCell<'_, B>) -> bool {
+    let a_start = a.as_ptr() as usize;
+    let b_start = b.as_ptr() as usize;
+    let a_end = a_start + core::mem::size_of::<A>();
+    let b_end = b_start + core::mem::size_of::<B>();
+
+    a_start != b_start && (a_end <= b_start || b_end <= a_start)
+}
+
 impl<'id, T> From<T> for GhostCell<'id, T> {
     #[inline]
     fn from(t: T) -> Self {
@@ -297,3 +403,91 @@ impl<'id, T: ?Sized> AsMut<T> for GhostCell<'id, T> {
         self.get_mut()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+
+    #[test]
+    fn borrow_mut_distinct() {
+        GhostToken::new(|mut token| {
+            let a = GhostCell::new(1);
+            let b = GhostCell::new(2);
+            let c = GhostCell::new(3);
+
+            let (x, y) = a.borrow_mut2(&b, &mut token).unwrap();
+            core::mem::swap(x, y);
+            assert_eq!((*a.borrow(&token), *b.borrow(&token)), (2, 1));
+
+            let (x, y, z) = a.borrow_mut3(&b, &c, &mut token).unwrap();
+            *x += 10;
+            *y += 20;
+            *z += 30;
+
+            for x in GhostCell::borrow_mut_array([&a, &b, &c], &mut token).unwrap() {
+                *x *= 2
+            }
+
+            let values = GhostCell::borrow_slice(&[a, b, c], &token).to_vec();
+            assert_eq!(values, [24, 42, 66]);
+        })
+    }
+
+    #[test]
+    fn borrow_mut_same_cell() {
+        GhostToken::new(|mut token| {
+            let a = GhostCell::new(1);
+            let b = GhostCell::new(2);
+
+            assert!(a.borrow_mut2(&a, &mut token).is_none());
+            assert!(a.borrow_mut3(&b, &a, &mut token).is_none());
+            assert!(a.borrow_mut3(&b, &b, &mut token).is_none());
+            assert!(GhostCell::borrow_mut_array([&a, &b, &a], &mut token).is_none());
+            assert!(GhostCell::borrow_mut_array([&b, &b], &mut token).is_none());
+        })
+    }
+
+    #[test]
+    fn borrow_mut_zero_sized() {
+        /// Puts the two cells at separate addresses.
+        #[repr(C)]
+        struct Cells<'id> {
+            a: GhostCell<'id, ()>,
+            x: u8,
+            b: GhostCell<'id, ()>,
+        }
+
+        GhostToken::new(|mut token| {
+            let cells = Cells {
+                a: GhostCell::new(()),
+                x: 0,
+                b: GhostCell::new(()),
+            };
+
+            assert_eq!(cells.x, 0);
+            assert!(cells.a.borrow_mut2(&cells.b, &mut token).is_some());
+            assert!(GhostCell::borrow_mut_array([&cells.a, &cells.b], &mut token).is_some());
+            assert!(cells.a.borrow_mut2(&cells.a, &mut token).is_none());
+        })
+    }
+
+    #[test]
+    fn par_chunks_of_borrowed_slice() {
+        GhostToken::new(|mut token| {
+            let cells: Vec<_> = (0..100).map(GhostCell::new).collect();
+
+            GhostCell::borrow_slice_mut(&cells, &mut token)
+                .par_chunks_mut(7)
+                .enumerate()
+                .for_each(|(i, chunk)| {
+                    for x in chunk {
+                        *x = *x * 10 + i
+                    }
+                });
+
+            let values = GhostCell::borrow_slice(&cells, &token);
+            assert!(values.iter().enumerate().all(|(i, &x)| x == i * 10 + i / 7));
+        })
+    }
+}
@@ -0,0 +1,322 @@
+use crate::{
+    clear::ClearMut,
+    ghost_cell::{GhostCell, GhostToken},
+    weak::WeakRefs,
+};
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    rc::{Rc, Weak},
+};
+
+/// A reference counted pointer to a value that's only accessible with the
+/// token of its brand.
+pub struct GhostRc<'brand, T>(Rc<GhostCell<'brand, T>>);
+
+impl<'brand, T> GhostRc<'brand, T> {
+    /// Creates a pointer that isn't registered with any `GhostHeap`, so
+    /// cycles through it are never collected.
+    pub fn new(value: T) -> Self {
+        Self(Rc::new(GhostCell::new(value)))
+    }
+
+    pub fn downgrade(&self) -> GhostWeak<'brand, T> {
+        GhostWeak(Rc::downgrade(&self.0))
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    pub fn as_ptr(&self) -> *const GhostCell<'brand, T> {
+        Rc::as_ptr(&self.0)
+    }
+}
+
+impl<'brand, T> Clone for GhostRc<'brand, T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<'brand, T> Deref for GhostRc<'brand, T> {
+    type Target = GhostCell<'brand, T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// A weak reference to a value that's only accessible with the token of its
+/// brand.
+pub struct GhostWeak<'brand, T>(Weak<GhostCell<'brand, T>>);
+
+impl<'brand, T> GhostWeak<'brand, T> {
+    pub fn upgrade(&self) -> Option<GhostRc<'brand, T>> {
+        self.0.upgrade().map(GhostRc)
+    }
+
+    /// Borrows the value if it hasn't been dropped. The returned borrow keeps
+    /// the value alive.
+    pub fn upgrade_borrow<'a>(
+        &self,
+        token: &'a GhostToken<'brand>,
+    ) -> Option<GhostBorrow<'a, 'brand, T>> {
+        Some(GhostBorrow {
+            value: self.upgrade()?,
+            token,
+        })
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.0.strong_count() == 0
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.0.ptr_eq(&other.0)
+    }
+}
+
+impl<'brand, T> Clone for GhostWeak<'brand, T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+/// A value borrowed from an upgraded `GhostWeak`.
+pub struct GhostBorrow<'a, 'brand, T> {
+    value: GhostRc<'brand, T>,
+    token: &'a GhostToken<'brand>,
+}
+
+impl<'a, 'brand, T> Deref for GhostBorrow<'a, 'brand, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.borrow(self.token)
+    }
+}
+
+/// Implemented by values that hold strong references to other values of the
+/// same type, so a `GhostHeap` can find cycles among them.
+///
+/// `GhostHeap::collect` frees a cycle by clearing its values, so their
+/// `ClearMut` impl must drop every reference `trace` reports, or the cycle
+/// leaks.
+pub trait Trace<'brand>: Sized {
+    /// Calls `f` with every strong reference held by the value.
+    fn trace(&self, f: &mut dyn FnMut(&GhostRc<'brand, Self>));
+}
+
+/// Keeps track of the values allocated through it, so that cycles of strong
+/// references between them that can no longer be reached from outside can be
+/// freed with `collect`.
+pub struct GhostHeap<'brand, T> {
+    objects: Vec<GhostWeak<'brand, T>>,
+}
+
+impl<'brand, T> GhostHeap<'brand, T> {
+    pub fn new() -> Self {
+        Self {
+            objects: Vec::new(),
+        }
+    }
+
+    pub fn alloc(&mut self, value: T) -> GhostRc<'brand, T> {
+        let x = GhostRc::new(value);
+        self.objects.push(x.downgrade());
+        x
+    }
+
+    /// The number of allocated values that haven't been freed yet.
+    pub fn len(&self) -> usize {
+        self.objects.live().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'brand, T: Trace<'brand> + ClearMut> GhostHeap<'brand, T> {
+    /// Frees the values that are only referenced by each other, by clearing
+    /// them, and returns how many they were.
+    ///
+    /// This is trial deletion: the references between the values are
+    /// subtracted from their reference counts, and the values left with a
+    /// count above zero are referenced from outside the heap. Everything they
+    /// reach is live, and the rest is garbage.
+    pub fn collect(&mut self, token: &mut GhostToken<'brand>) -> usize {
+        self.objects.prune_dead();
+
+        let objects: Vec<_> = self.objects.live().collect();
+
+        let indexes: HashMap<_, _> = objects
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (x.as_ptr(), i))
+            .collect();
+
+        // Not counting the references in `objects`
+        let mut counts: Vec<_> = objects.iter().map(|x| Rc::strong_count(&x.0) - 1).collect();
+
+        for x in objects.iter() {
+            x.borrow(token).trace(&mut |y| {
+                if let Some(&i) = indexes.get(&y.as_ptr()) {
+                    counts[i] = counts[i].saturating_sub(1);
+                }
+            });
+        }
+
+        let mut live: Vec<_> = counts.iter().map(|&x| x > 0).collect();
+        let mut stack: Vec<_> = (0..objects.len()).filter(|&i| live[i]).collect();
+
+        while let Some(i) = stack.pop() {
+            objects[i].borrow(token).trace(&mut |y| {
+                if let Some(&j) = indexes.get(&y.as_ptr()) {
+                    if !live[j] {
+                        live[j] = true;
+                        stack.push(j);
+                    }
+                }
+            });
+        }
+
+        // Clearing the garbage drops the references between them, and
+        // dropping `objects` then frees them
+        let mut freed = 0;
+
+        for (x, _) in objects.iter().zip(live).filter(|(_, live)| !live) {
+            x.borrow_mut(token).clear_mut();
+            freed += 1;
+        }
+
+        drop(objects);
+        self.objects.prune_dead();
+        freed
+    }
+}
+
+impl<'brand, T> Default for GhostHeap<'brand, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::Error,
+        ghost_rc::GhostRcBackend,
+        model::{Model, ModelBackend, Scenario},
+    };
+
+    #[derive(Default, ClearMut)]
+    struct Node<'brand> {
+        next: Vec<GhostRc<'brand, Node<'brand>>>,
+    }
+
+    impl<'brand> Trace<'brand> for Node<'brand> {
+        fn trace(&self, f: &mut dyn FnMut(&GhostRc<'brand, Self>)) {
+            for x in self.next.iter() {
+                f(x)
+            }
+        }
+    }
+
+    type Heap<'brand> = GhostHeap<'brand, Node<'brand>>;
+    type NodeRef<'brand> = GhostRc<'brand, Node<'brand>>;
+
+    fn link<'brand>(token: &mut GhostToken<'brand>, a: &NodeRef<'brand>, b: &NodeRef<'brand>) {
+        a.borrow_mut(token).next.push(b.clone())
+    }
+
+    /// Allocates two nodes referring to each other.
+    fn cycle<'brand>(
+        token: &mut GhostToken<'brand>,
+        heap: &mut Heap<'brand>,
+    ) -> (NodeRef<'brand>, NodeRef<'brand>) {
+        let a = heap.alloc(Node::default());
+        let b = heap.alloc(Node::default());
+        link(token, &a, &b);
+        link(token, &b, &a);
+        (a, b)
+    }
+
+    #[test]
+    fn collect_unreachable_cycle() {
+        GhostToken::new(|mut token| {
+            let mut heap = Heap::new();
+            cycle(&mut token, &mut heap);
+
+            assert_eq!(heap.len(), 2);
+            assert_eq!(heap.collect(&mut token), 2);
+            assert_eq!(heap.len(), 0);
+            assert!(heap.is_empty());
+        })
+    }
+
+    #[test]
+    fn keep_reachable_cycle() {
+        GhostToken::new(|mut token| {
+            let mut heap = Heap::new();
+            let (a, _) = cycle(&mut token, &mut heap);
+
+            assert_eq!(heap.collect(&mut token), 0);
+            assert_eq!(heap.len(), 2);
+
+            drop(a);
+            assert_eq!(heap.collect(&mut token), 2);
+            assert!(heap.is_empty());
+        })
+    }
+
+    #[test]
+    fn keep_cycle_reachable_through_chain() {
+        GhostToken::new(|mut token| {
+            let mut heap = Heap::new();
+            let (a, _) = cycle(&mut token, &mut heap);
+            let c = heap.alloc(Node::default());
+            let d = heap.alloc(Node::default());
+            link(&mut token, &d, &c);
+            link(&mut token, &c, &a);
+            drop(a);
+            drop(c);
+
+            assert_eq!(heap.collect(&mut token), 0);
+            assert_eq!(heap.len(), 4);
+
+            // Dropping the outside reference frees the chain, and the cycle
+            // is left for `collect`
+            drop(d);
+            assert_eq!(heap.len(), 2);
+            assert_eq!(heap.collect(&mut token), 2);
+            assert!(heap.is_empty());
+        })
+    }
+
+    /// Makes friendship cycles, including a player who is their own friend.
+    struct Friends;
+
+    impl Scenario for Friends {
+        type Output = Result<(), Error>;
+
+        fn run<M: Model>(self, m: &mut M) -> Self::Output {
+            let p1 = m.create_player("Eric", 10)?;
+            let p2 = m.create_player("Tom", 15)?;
+            let p3 = m.create_player("Carl", 17)?;
+            m.make_friends(&p1, &p2)?;
+            m.make_friends(&p2, &p3)?;
+            m.make_friends(&p3, &p1)?;
+            m.make_friends(&p3, &p3)?;
+            m.remove_player(&p2)
+        }
+    }
+
+    #[test]
+    fn ghost_rc_backend_frees_players() {
+        // `run` asserts that its heap is empty when it's done
+        GhostRcBackend::run(Friends).unwrap();
+    }
+}
@@ -0,0 +1,312 @@
+use crate::ghost_cell::{GhostCell, GhostToken};
+use std::{
+    mem,
+    rc::{Rc, Weak},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// The id of the next graph created. Ids start at 1, as 0 marks a removed
+/// node.
+static NEXT_GRAPH_ID: AtomicUsize = AtomicUsize::new(1);
+
+type NodeCell<'brand, N, E> = GhostCell<'brand, NodeData<'brand, N, E>>;
+
+struct NodeData<'brand, N, E> {
+    value: N,
+    edges: Vec<(Weak<NodeCell<'brand, N, E>>, E)>,
+    /// The sources of the edges to this node, one entry per edge.
+    sources: Vec<Weak<NodeCell<'brand, N, E>>>,
+    /// The id of the graph the node is in, or 0 once it has been removed.
+    graph: usize,
+    /// The position of the node in the node list of its graph.
+    index: usize,
+}
+
+/// A node of a `GhostGraph`, whose value and edges are only accessible with
+/// the token of the graph's brand.
+pub struct GhostNode<'brand, N, E>(Rc<NodeCell<'brand, N, E>>);
+
+impl<'brand, N, E> GhostNode<'brand, N, E> {
+    pub fn value<'a>(&'a self, token: &'a GhostToken<'brand>) -> &'a N {
+        &self.0.borrow(token).value
+    }
+
+    pub fn value_mut<'a>(&'a self, token: &'a mut GhostToken<'brand>) -> &'a mut N {
+        &mut self.0.borrow_mut(token).value
+    }
+
+    /// The targets and values of the edges from this node.
+    pub fn edges<'a>(
+        &'a self,
+        token: &'a GhostToken<'brand>,
+    ) -> impl Iterator<Item = (GhostNode<'brand, N, E>, &'a E)> + 'a {
+        self.0
+            .borrow(token)
+            .edges
+            .iter()
+            .filter_map(|(x, e)| Some((GhostNode(x.upgrade()?), e)))
+    }
+
+    /// The sources of the edges to this node.
+    pub fn sources<'a>(
+        &'a self,
+        token: &'a GhostToken<'brand>,
+    ) -> impl Iterator<Item = GhostNode<'brand, N, E>> + 'a {
+        self.0
+            .borrow(token)
+            .sources
+            .iter()
+            .filter_map(|x| Some(GhostNode(x.upgrade()?)))
+    }
+
+    fn is(&self, x: &Weak<NodeCell<'brand, N, E>>) -> bool {
+        Weak::as_ptr(x) == Rc::as_ptr(&self.0)
+    }
+}
+
+impl<'brand, N, E> Clone for GhostNode<'brand, N, E> {
+    fn clone(&self) -> Self {
+        GhostNode(self.0.clone())
+    }
+}
+
+impl<'brand, N, E> PartialEq for GhostNode<'brand, N, E> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<'brand, N, E> Eq for GhostNode<'brand, N, E> {}
+
+/// A directed graph with values on nodes and edges, kept in adjacency lists
+/// inside `GhostCell`s. The graph owns the nodes and the edges refer to them
+/// weakly, so cycles are fine.
+pub struct GhostGraph<'brand, N, E> {
+    id: usize,
+    nodes: Vec<GhostNode<'brand, N, E>>,
+}
+
+impl<'brand, N, E> GhostGraph<'brand, N, E> {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_GRAPH_ID.fetch_add(1, Ordering::Relaxed),
+            nodes: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &GhostNode<'brand, N, E>> {
+        self.nodes.iter()
+    }
+
+    /// Whether `node` was added to this graph and hasn't been removed.
+    pub fn contains(&self, token: &GhostToken<'brand>, node: &GhostNode<'brand, N, E>) -> bool {
+        node.0.borrow(token).graph == self.id
+    }
+
+    pub fn add_node(&mut self, value: N) -> GhostNode<'brand, N, E> {
+        let node = GhostNode(Rc::new(GhostCell::new(NodeData {
+            value,
+            edges: Vec::new(),
+            sources: Vec::new(),
+            graph: self.id,
+            index: self.nodes.len(),
+        })));
+
+        self.nodes.push(node.clone());
+        node
+    }
+
+    /// Adds an edge from `from` to `to`, returning `false` without adding it
+    /// if either node isn't in the graph. Nodes can have several edges
+    /// between them, and edges to themselves.
+    pub fn add_edge(
+        &self,
+        token: &mut GhostToken<'brand>,
+        from: &GhostNode<'brand, N, E>,
+        to: &GhostNode<'brand, N, E>,
+        value: E,
+    ) -> bool {
+        if !self.contains(token, from) || !self.contains(token, to) {
+            return false;
+        }
+
+        from.0
+            .borrow_mut(token)
+            .edges
+            .push((Rc::downgrade(&to.0), value));
+
+        to.0.borrow_mut(token).sources.push(Rc::downgrade(&from.0));
+        true
+    }
+
+    /// Removes an edge from `from` to `to`, returning its value.
+    pub fn remove_edge(
+        &self,
+        token: &mut GhostToken<'brand>,
+        from: &GhostNode<'brand, N, E>,
+        to: &GhostNode<'brand, N, E>,
+    ) -> Option<E> {
+        let edges = &mut from.0.borrow_mut(token).edges;
+        let i = edges.iter().position(|(x, _)| to.is(x))?;
+        let (_, value) = edges.remove(i);
+
+        let sources = &mut to.0.borrow_mut(token).sources;
+
+        if let Some(i) = sources.iter().position(|x| from.is(x)) {
+            sources.swap_remove(i);
+        }
+
+        Some(value)
+    }
+
+    /// Removes the node and all edges from and to it, returning `false` if it
+    /// wasn't in the graph. Its value stays accessible through `node`.
+    pub fn remove_node(
+        &mut self,
+        token: &mut GhostToken<'brand>,
+        node: &GhostNode<'brand, N, E>,
+    ) -> bool {
+        let n = node.0.borrow_mut(token);
+
+        if n.graph != self.id {
+            return false;
+        }
+
+        n.graph = 0;
+        let i = n.index;
+        let edges = mem::take(&mut n.edges);
+        let sources = mem::take(&mut n.sources);
+
+        self.nodes.swap_remove(i);
+
+        if let Some(x) = self.nodes.get(i) {
+            x.0.borrow_mut(token).index = i;
+        }
+
+        for (x, _) in edges {
+            if let Some(x) = x.upgrade() {
+                x.borrow_mut(token).sources.retain(|x| !node.is(x));
+            }
+        }
+
+        for x in sources {
+            if let Some(x) = x.upgrade() {
+                x.borrow_mut(token).edges.retain(|(x, _)| !node.is(x));
+            }
+        }
+
+        true
+    }
+}
+
+impl<'brand, N, E> Default for GhostGraph<'brand, N, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_edge_to_foreign_node() {
+        GhostToken::new(|mut token| {
+            let mut graph1 = GhostGraph::new();
+            let mut graph2 = GhostGraph::new();
+            let a = graph1.add_node("a");
+            let b = graph1.add_node("b");
+            let c = graph2.add_node("c");
+
+            assert!(graph1.add_edge(&mut token, &a, &b, 1));
+            assert!(!graph1.add_edge(&mut token, &a, &c, 2));
+            assert!(!graph1.add_edge(&mut token, &c, &b, 3));
+
+            graph1.remove_node(&mut token, &b);
+            assert!(!graph1.add_edge(&mut token, &a, &b, 4));
+
+            assert_eq!(a.edges(&token).count(), 0);
+            assert_eq!(c.edges(&token).count(), 0);
+            assert_eq!(c.sources(&token).count(), 0);
+        })
+    }
+
+    /// The values of the targets of the edges from `node`, sorted.
+    fn targets<'brand>(
+        node: &GhostNode<'brand, &'static str, i32>,
+        token: &GhostToken<'brand>,
+    ) -> Vec<&'static str> {
+        let mut v: Vec<_> = node.edges(token).map(|(x, _)| *x.value(token)).collect();
+        v.sort();
+        v
+    }
+
+    #[test]
+    fn remove_node() {
+        GhostToken::new(|mut token| {
+            let mut graph = GhostGraph::new();
+            let a = graph.add_node("a");
+            let b = graph.add_node("b");
+            let c = graph.add_node("c");
+
+            graph.add_edge(&mut token, &a, &b, 1);
+            graph.add_edge(&mut token, &b, &a, 2);
+            graph.add_edge(&mut token, &b, &b, 3);
+            graph.add_edge(&mut token, &c, &b, 4);
+            graph.add_edge(&mut token, &c, &a, 5);
+
+            assert!(graph.remove_node(&mut token, &b));
+            assert!(!graph.remove_node(&mut token, &b));
+            assert!(!graph.contains(&token, &b));
+            assert_eq!(graph.len(), 2);
+
+            assert_eq!(targets(&a, &token), Vec::<&str>::new());
+            assert_eq!(targets(&c, &token), ["a"]);
+            assert_eq!(a.sources(&token).count(), 1);
+            assert_eq!(b.edges(&token).count(), 0);
+            assert_eq!(b.sources(&token).count(), 0);
+            assert_eq!(*b.value(&token), "b");
+
+            // The node moved into the removed one's place can still be found
+            assert!(graph.contains(&token, &c));
+            assert!(graph.remove_node(&mut token, &c));
+            assert_eq!(a.sources(&token).count(), 0);
+            assert!(graph.remove_node(&mut token, &a));
+            assert!(graph.is_empty());
+        })
+    }
+
+    #[test]
+    fn remove_edge() {
+        GhostToken::new(|mut token| {
+            let mut graph = GhostGraph::new();
+            let a = graph.add_node("a");
+            let b = graph.add_node("b");
+
+            graph.add_edge(&mut token, &a, &b, 1);
+            graph.add_edge(&mut token, &a, &b, 2);
+            graph.add_edge(&mut token, &a, &a, 3);
+
+            assert_eq!(graph.remove_edge(&mut token, &a, &b), Some(1));
+            assert_eq!(b.sources(&token).count(), 1);
+            assert_eq!(graph.remove_edge(&mut token, &a, &b), Some(2));
+            assert_eq!(graph.remove_edge(&mut token, &a, &b), None);
+            assert_eq!(b.sources(&token).count(), 0);
+
+            assert_eq!(graph.remove_edge(&mut token, &a, &a), Some(3));
+            assert_eq!(a.edges(&token).count(), 0);
+            assert_eq!(a.sources(&token).count(), 0);
+
+            graph.remove_node(&mut token, &b);
+            assert_eq!(graph.remove_edge(&mut token, &a, &b), None);
+        })
+    }
+}
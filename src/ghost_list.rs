@@ -0,0 +1,381 @@
+use crate::ghost_cell::{GhostCell, GhostToken};
+use std::rc::{Rc, Weak};
+
+type Link<'brand, T> = Rc<GhostCell<'brand, Node<'brand, T>>>;
+
+struct Node<'brand, T> {
+    value: T,
+    prev: Option<Weak<GhostCell<'brand, Node<'brand, T>>>>,
+    next: Option<Link<'brand, T>>,
+}
+
+/// A doubly linked list whose nodes are only accessible with the token of
+/// its brand. Each node is owned by its predecessor and refers weakly back to
+/// it, so no `RefCell` is needed to update the links on both sides.
+pub struct GhostLinkedList<'brand, T> {
+    head: Option<Link<'brand, T>>,
+    tail: Option<Link<'brand, T>>,
+    len: usize,
+}
+
+impl<'brand, T> GhostLinkedList<'brand, T> {
+    pub fn new() -> Self {
+        Self {
+            head: None,
+            tail: None,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push_front(&mut self, token: &mut GhostToken<'brand>, value: T) {
+        let next = self.head.clone();
+        self.insert_between(token, None, next, value);
+    }
+
+    pub fn push_back(&mut self, token: &mut GhostToken<'brand>, value: T) {
+        let prev = self.tail.clone();
+        self.insert_between(token, prev, None, value);
+    }
+
+    pub fn pop_front(&mut self, token: &mut GhostToken<'brand>) -> Option<T> {
+        let node = self.head.clone()?;
+        Some(self.unlink(token, node))
+    }
+
+    pub fn pop_back(&mut self, token: &mut GhostToken<'brand>) -> Option<T> {
+        let node = self.tail.clone()?;
+        Some(self.unlink(token, node))
+    }
+
+    pub fn front<'a>(&'a self, token: &'a GhostToken<'brand>) -> Option<&'a T> {
+        self.head.as_ref().map(|x| &x.borrow(token).value)
+    }
+
+    pub fn back<'a>(&'a self, token: &'a GhostToken<'brand>) -> Option<&'a T> {
+        self.tail.as_ref().map(|x| &x.borrow(token).value)
+    }
+
+    pub fn iter<'a>(&'a self, token: &'a GhostToken<'brand>) -> Iter<'a, 'brand, T> {
+        Iter {
+            next: self.head.as_ref(),
+            token,
+        }
+    }
+
+    /// A cursor at the first item, or at the "ghost" position between the
+    /// last and the first item if the list is empty.
+    pub fn cursor_front(&mut self) -> CursorMut<'_, 'brand, T> {
+        let current = self.head.clone();
+        CursorMut {
+            list: self,
+            current,
+        }
+    }
+
+    /// A cursor at the last item, or at the ghost position if the list is
+    /// empty.
+    pub fn cursor_back(&mut self) -> CursorMut<'_, 'brand, T> {
+        let current = self.tail.clone();
+        CursorMut {
+            list: self,
+            current,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.tail = None;
+        self.len = 0;
+
+        // Unlink the nodes one at a time instead of recursively dropping them
+        let mut next = self.head.take();
+
+        while let Some(node) = next {
+            next = match Rc::try_unwrap(node) {
+                Ok(x) => x.into_inner().next,
+                Err(_) => None,
+            };
+        }
+    }
+
+    fn insert_between(
+        &mut self,
+        token: &mut GhostToken<'brand>,
+        prev: Option<Link<'brand, T>>,
+        next: Option<Link<'brand, T>>,
+        value: T,
+    ) {
+        let node = Rc::new(GhostCell::new(Node {
+            value,
+            prev: prev.as_ref().map(Rc::downgrade),
+            next: next.clone(),
+        }));
+
+        match prev {
+            Some(p) => p.borrow_mut(token).next = Some(node.clone()),
+            None => self.head = Some(node.clone()),
+        }
+
+        match next {
+            Some(n) => n.borrow_mut(token).prev = Some(Rc::downgrade(&node)),
+            None => self.tail = Some(node),
+        }
+
+        self.len += 1;
+    }
+
+    fn unlink(&mut self, token: &mut GhostToken<'brand>, node: Link<'brand, T>) -> T {
+        let n = node.borrow_mut(token);
+        let prev = n.prev.take().and_then(|x| x.upgrade());
+        let next = n.next.take();
+
+        match &next {
+            Some(x) => x.borrow_mut(token).prev = prev.as_ref().map(Rc::downgrade),
+            None => self.tail = prev.clone(),
+        }
+
+        match prev {
+            Some(x) => x.borrow_mut(token).next = next,
+            None => self.head = next,
+        }
+
+        self.len -= 1;
+
+        match Rc::try_unwrap(node) {
+            Ok(x) => x.into_inner().value,
+            Err(_) => unreachable!("Node still linked!"),
+        }
+    }
+}
+
+impl<'brand, T> Default for GhostLinkedList<'brand, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'brand, T> Drop for GhostLinkedList<'brand, T> {
+    fn drop(&mut self) {
+        self.clear()
+    }
+}
+
+pub struct Iter<'a, 'brand, T> {
+    next: Option<&'a Link<'brand, T>>,
+    token: &'a GhostToken<'brand>,
+}
+
+impl<'a, 'brand, T> Iterator for Iter<'a, 'brand, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.next?.borrow(self.token);
+        self.next = node.next.as_ref();
+        Some(&node.value)
+    }
+}
+
+/// Points at an item of a list, or at the ghost position between the last
+/// and the first item, and can insert and remove items around it.
+pub struct CursorMut<'a, 'brand, T> {
+    list: &'a mut GhostLinkedList<'brand, T>,
+    current: Option<Link<'brand, T>>,
+}
+
+impl<'a, 'brand, T> CursorMut<'a, 'brand, T> {
+    /// The item at the cursor, or `None` at the ghost position.
+    pub fn current<'b>(&'b self, token: &'b GhostToken<'brand>) -> Option<&'b T> {
+        self.current.as_ref().map(|x| &x.borrow(token).value)
+    }
+
+    pub fn current_mut<'b>(&'b self, token: &'b mut GhostToken<'brand>) -> Option<&'b mut T> {
+        match &self.current {
+            Some(x) => Some(&mut x.borrow_mut(token).value),
+            None => None,
+        }
+    }
+
+    /// Moves to the next item, wrapping around through the ghost position.
+    pub fn move_next(&mut self, token: &GhostToken<'brand>) {
+        self.current = match &self.current {
+            Some(x) => x.borrow(token).next.clone(),
+            None => self.list.head.clone(),
+        };
+    }
+
+    /// Moves to the previous item, wrapping around through the ghost
+    /// position.
+    pub fn move_prev(&mut self, token: &GhostToken<'brand>) {
+        self.current = match &self.current {
+            Some(x) => x.borrow(token).prev.as_ref().and_then(|x| x.upgrade()),
+            None => self.list.tail.clone(),
+        };
+    }
+
+    /// Inserts `value` before the cursor, i.e. at the back at the ghost
+    /// position.
+    pub fn insert_before(&mut self, token: &mut GhostToken<'brand>, value: T) {
+        let prev = match &self.current {
+            Some(x) => x.borrow(token).prev.as_ref().and_then(|x| x.upgrade()),
+            None => self.list.tail.clone(),
+        };
+
+        let next = self.current.clone();
+        self.list.insert_between(token, prev, next, value);
+    }
+
+    /// Inserts `value` after the cursor, i.e. at the front at the ghost
+    /// position.
+    pub fn insert_after(&mut self, token: &mut GhostToken<'brand>, value: T) {
+        let next = match &self.current {
+            Some(x) => x.borrow(token).next.clone(),
+            None => self.list.head.clone(),
+        };
+
+        let prev = self.current.clone();
+        self.list.insert_between(token, prev, next, value);
+    }
+
+    /// Removes the item at the cursor and moves to the next one. Does
+    /// nothing at the ghost position.
+    pub fn remove_current(&mut self, token: &mut GhostToken<'brand>) -> Option<T> {
+        let node = self.current.take()?;
+        self.current = node.borrow(token).next.clone();
+        Some(self.list.unlink(token, node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list<'brand>(
+        token: &mut GhostToken<'brand>,
+        values: &[i32],
+    ) -> GhostLinkedList<'brand, i32> {
+        let mut l = GhostLinkedList::new();
+
+        for &x in values {
+            l.push_back(token, x)
+        }
+
+        l
+    }
+
+    fn values<'brand>(l: &GhostLinkedList<'brand, i32>, token: &GhostToken<'brand>) -> Vec<i32> {
+        l.iter(token).copied().collect()
+    }
+
+    #[test]
+    fn push_and_pop() {
+        GhostToken::new(|mut token| {
+            let mut l = list(&mut token, &[2, 3]);
+            l.push_front(&mut token, 1);
+            assert_eq!(values(&l, &token), [1, 2, 3]);
+            assert_eq!((l.front(&token), l.back(&token)), (Some(&1), Some(&3)));
+
+            assert_eq!(l.pop_back(&mut token), Some(3));
+            assert_eq!(l.pop_front(&mut token), Some(1));
+            assert_eq!(l.pop_front(&mut token), Some(2));
+            assert_eq!(l.pop_front(&mut token), None);
+            assert_eq!(l.pop_back(&mut token), None);
+            assert!(l.is_empty());
+            assert_eq!(l.front(&token), None);
+        })
+    }
+
+    #[test]
+    fn cursor_moves_through_ghost() {
+        GhostToken::new(|mut token| {
+            let mut l = list(&mut token, &[1, 2]);
+            let mut c = l.cursor_front();
+            assert_eq!(c.current(&token), Some(&1));
+
+            c.move_prev(&token);
+            assert_eq!(c.current(&token), None);
+            c.move_prev(&token);
+            assert_eq!(c.current(&token), Some(&2));
+            c.move_next(&token);
+            assert_eq!(c.current(&token), None);
+            c.move_next(&token);
+            assert_eq!(c.current(&token), Some(&1));
+
+            *c.current_mut(&mut token).unwrap() = 10;
+            assert_eq!(values(&l, &token), [10, 2]);
+        })
+    }
+
+    #[test]
+    fn cursor_insert() {
+        GhostToken::new(|mut token| {
+            let mut l = list(&mut token, &[2]);
+            let mut c = l.cursor_front();
+            c.insert_before(&mut token, 1);
+            c.insert_after(&mut token, 3);
+            assert_eq!(c.current(&token), Some(&2));
+
+            // At the ghost position, inserting before appends and inserting
+            // after prepends
+            c.move_prev(&token);
+            c.move_prev(&token);
+            assert_eq!(c.current(&token), None);
+            c.insert_before(&mut token, 4);
+            c.insert_after(&mut token, 0);
+
+            assert_eq!(values(&l, &token), [0, 1, 2, 3, 4]);
+            assert_eq!(l.len(), 5);
+            assert_eq!((l.front(&token), l.back(&token)), (Some(&0), Some(&4)));
+
+            // The back links are kept up to date too
+            let mut c = l.cursor_back();
+            let mut backwards = Vec::new();
+
+            while let Some(&x) = c.current(&token) {
+                backwards.push(x);
+                c.move_prev(&token);
+            }
+
+            assert_eq!(backwards, [4, 3, 2, 1, 0]);
+        })
+    }
+
+    #[test]
+    fn cursor_remove() {
+        GhostToken::new(|mut token| {
+            let mut l = list(&mut token, &[1, 2, 3]);
+            let mut c = l.cursor_front();
+            c.move_next(&token);
+            assert_eq!(c.remove_current(&mut token), Some(2));
+            assert_eq!(c.current(&token), Some(&3));
+
+            // Removing the last item moves to the ghost position
+            assert_eq!(c.remove_current(&mut token), Some(3));
+            assert_eq!(c.current(&token), None);
+            assert_eq!(c.remove_current(&mut token), None);
+
+            c.move_prev(&token);
+            assert_eq!(c.remove_current(&mut token), Some(1));
+            assert_eq!(c.current(&token), None);
+
+            assert!(l.is_empty());
+            assert_eq!((l.front(&token), l.back(&token)), (None, None));
+            l.push_back(&mut token, 4);
+            assert_eq!(values(&l, &token), [4]);
+        })
+    }
+
+    #[test]
+    fn drop_long_list() {
+        GhostToken::new(|mut token| {
+            let l = list(&mut token, &vec![0; 1_000_000]);
+            assert_eq!(l.len(), 1_000_000);
+        })
+    }
+}
@@ -1,95 +1,130 @@
 use crate::{
-    cell_pool::CellPool,
-    ghost_cell::{GhostCell, GhostToken},
+    branded_pool::{BrandedIndex, GhostPool, PoolBrand},
+    cell_pool::PoolError,
+    error::Error,
+    ghost_cell::GhostToken,
+    model::{Model, ModelBackend, Scenario},
 };
 
-struct GCell<'brand, T>(GhostCell<'brand, T>);
+struct Player<'brand> {
+    name: String,
+    health: i32,
+    friends: Vec<PlayerRef<'brand>>,
+}
 
-impl<'brand, T> GCell<'brand, T> {
-    fn new(value: T) -> Self {
-        Self(GhostCell::new(value))
+impl<'brand> Player<'brand> {
+    fn new(name: &str, health: i32) -> Self {
+        Self {
+            name: name.to_owned(),
+            health,
+            friends: Vec::new(),
+        }
     }
+}
 
-    fn borrow<'a>(&'a self, token: &'a GhostToken<'brand>) -> &'a T {
-        self.0.borrow(token)
+type PlayerRef<'brand> = BrandedIndex<'brand, Player<'brand>>;
+
+type Players<'brand> = GhostPool<'brand, Player<'brand>>;
+
+fn make_friends<'brand>(
+    token: &mut GhostToken<'brand>,
+    players: &Players<'brand>,
+    player1: PlayerRef<'brand>,
+    player2: PlayerRef<'brand>,
+) {
+    if players.get(player1, token).friends.contains(&player2) {
+        return;
     }
 
-    fn borrow_mut<'a>(&'a self, token: &'a mut GhostToken<'brand>) -> &'a mut T {
-        self.0.borrow_mut(token)
+    // Befriending oneself only adds a single link
+    if let Some((p1, p2)) = players.get_mut2(player1, player2, token) {
+        p1.friends.push(player2);
+        p2.friends.push(player1);
+    } else {
+        players.get_mut(player1, token).friends.push(player2);
     }
 }
 
-impl<'brand, T: Default> Default for GCell<'brand, T> {
-    fn default() -> Self {
-        Self::new(Default::default())
+fn remove_player<'brand>(
+    token: &mut GhostToken<'brand>,
+    players: &Players<'brand>,
+    player: PlayerRef<'brand>,
+) -> Result<(), PoolError> {
+    let p = players.free(token, player)?;
+
+    // A player can be their own friend, but is already gone
+    for f in p.friends.into_iter().filter(|&f| f != player) {
+        players.get_mut(f, token).friends.retain(|x| *x != player);
     }
+
+    Ok(())
 }
 
-type Ref<'t, 'brand, T> = &'t GCell<'brand, T>;
+pub struct GhostPoolBackend;
 
-#[derive(Default)]
-struct Player<'t, 'brand> {
-    game: Option<GameRef<'t, 'brand>>,
-    name: String,
-    health: i32,
-    friends: Vec<PlayerRef<'t, 'brand>>,
+struct GhostPoolModel<'brand> {
+    token: GhostToken<'brand>,
+    players: Players<'brand>,
 }
 
-impl<'t, 'brand> Player<'t, 'brand> {
-    fn init(&mut self, game: GameRef<'t, 'brand>, name: &str, health: i32) {
-        self.game = Some(game);
-        self.name = name.to_owned();
-        self.health = health;
-    }
-}
+impl<'brand> Model for GhostPoolModel<'brand> {
+    type Player = PlayerRef<'brand>;
 
-type PlayerRef<'t, 'brand> = Ref<'t, 'brand, Player<'t, 'brand>>;
+    fn create_player(&mut self, name: &str, health: i32) -> Result<PlayerRef<'brand>, Error> {
+        Ok(self
+            .players
+            .alloc(&mut self.token, Player::new(name, health)))
+    }
 
-fn make_friends<'t, 'brand>(
-    token: &mut GhostToken<'brand>,
-    player1: PlayerRef<'t, 'brand>,
-    player2: PlayerRef<'t, 'brand>,
-) {
-    player1.borrow_mut(token).friends.push(player2);
-    player2.borrow_mut(token).friends.push(player1);
-}
+    fn make_friends(
+        &mut self,
+        player1: &PlayerRef<'brand>,
+        player2: &PlayerRef<'brand>,
+    ) -> Result<(), Error> {
+        make_friends(&mut self.token, &self.players, *player1, *player2);
+        Ok(())
+    }
 
-struct Game<'t, 'brand> {
-    players: &'t CellPool<GCell<'brand, Player<'t, 'brand>>>,
-}
+    fn with_name<R>(&self, player: &PlayerRef<'brand>, f: impl FnOnce(&str) -> R) -> R {
+        f(&self.players.get(*player, &self.token).name)
+    }
 
-type GameRef<'t, 'brand> = Ref<'t, 'brand, Game<'t, 'brand>>;
+    fn health(&self, player: &PlayerRef<'brand>) -> i32 {
+        self.players.get(*player, &self.token).health
+    }
 
-fn create_player<'t, 'brand>(
-    token: &mut GhostToken<'brand>,
-    game: GameRef<'t, 'brand>,
-    name: &str,
-    health: i32,
-) -> Result<PlayerRef<'t, 'brand>, &'static str> {
-    let p = game.borrow_mut(token).players.alloc()?;
-    p.borrow_mut(token).init(game, name, health);
-    Ok(p)
-}
+    fn set_health(&mut self, player: &PlayerRef<'brand>, health: i32) {
+        self.players.get_mut(*player, &mut self.token).health = health;
+    }
 
-pub fn run_game() -> Result<(), &'static str> {
-    GhostToken::new(|mut token| {
-        let players = CellPool::new(100);
-        let game = GCell::new(Game { players: &players });
-        let t = &mut token;
+    fn for_each_friend(&self, player: &PlayerRef<'brand>, mut f: impl FnMut(&PlayerRef<'brand>)) {
+        for x in self.players.get(*player, &self.token).friends.iter() {
+            f(x)
+        }
+    }
 
-        let p1 = create_player(t, &game, "Eric", 10)?;
-        let p2 = create_player(t, &game, "Tom", 15)?;
-        let p3 = create_player(t, &game, "Carl", 17)?;
+    fn for_each_player(&self, mut f: impl FnMut(&PlayerRef<'brand>)) {
+        for x in self.players.indexes(&self.token) {
+            f(&x)
+        }
+    }
 
-        make_friends(t, &p1, &p2);
-        make_friends(t, &p1, &p3);
+    fn player_id(&self, player: &PlayerRef<'brand>) -> usize {
+        player.index()
+    }
 
-        p2.borrow_mut(t).health = 20;
+    fn remove_player(&mut self, player: &PlayerRef<'brand>) -> Result<(), Error> {
+        Ok(remove_player(&mut self.token, &self.players, *player)?)
+    }
+}
 
-        for x in p1.borrow(t).friends.iter() {
-            println!("{}: {}", x.borrow(t).name, x.borrow(t).health)
-        }
+impl ModelBackend for GhostPoolBackend {
+    const NAME: &'static str = "Ghost pool";
 
-        Ok(())
-    })
+    fn run<S: Scenario>(scenario: S) -> S::Output {
+        PoolBrand::new(|brand, token| {
+            let players = GhostPool::new(brand);
+            scenario.run(&mut GhostPoolModel { token, players })
+        })
+    }
 }
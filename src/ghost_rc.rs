@@ -1,55 +1,53 @@
-use std::{
-    ops::Deref,
-    rc::{Rc, Weak},
+use std::mem;
+
+use crate::{
+    clear::ClearMut,
+    error::Error,
+    ghost_cell::GhostToken,
+    ghost_gc::{GhostHeap, GhostRc, Trace},
+    model::{Model, ModelBackend, Scenario},
 };
 
-use crate::ghost_cell::{GhostCell, GhostToken};
-
-struct Ref<'brand, T>(Rc<GhostCell<'brand, T>>);
-
-impl<'brand, T> Ref<'brand, T> {
-    fn new(v: T) -> Self {
-        Self(Rc::new(GhostCell::new(v)))
-    }
-
-    fn weak(&self) -> Weak<GhostCell<'brand, T>> {
-        Rc::downgrade(&self.0)
-    }
-}
-
-impl<'brand, T> Clone for Ref<'brand, T> {
-    fn clone(&self) -> Self {
-        Ref(self.0.clone())
-    }
-}
-
-impl<'brand, T> Deref for Ref<'brand, T> {
-    type Target = GhostCell<'brand, T>;
-
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
-}
-
-type WRef<'brand, T> = Weak<GhostCell<'brand, T>>;
+type Ref<'brand, T> = GhostRc<'brand, T>;
 
+#[derive(ClearMut)]
 struct Player<'brand> {
-    game: GameWRef<'brand>,
     name: String,
     health: i32,
-    friends: Vec<PlayerWRef<'brand>>,
+    friends: Vec<PlayerRef<'brand>>,
+}
+
+impl<'brand> Trace<'brand> for Player<'brand> {
+    fn trace(&self, f: &mut dyn FnMut(&PlayerRef<'brand>)) {
+        for x in self.friends.iter() {
+            f(x)
+        }
+    }
 }
 
 type PlayerRef<'brand> = Ref<'brand, Player<'brand>>;
-type PlayerWRef<'brand> = WRef<'brand, Player<'brand>>;
 
 fn make_friends<'brand>(
     token: &mut GhostToken<'brand>,
     player1: &PlayerRef<'brand>,
     player2: &PlayerRef<'brand>,
 ) {
-    player1.borrow_mut(token).friends.push(player2.weak());
-    player2.borrow_mut(token).friends.push(player1.weak());
+    if player1
+        .borrow(token)
+        .friends
+        .iter()
+        .any(|x| x.ptr_eq(player2))
+    {
+        return;
+    }
+
+    // Befriending oneself only adds a single link
+    if let Some((p1, p2)) = player1.borrow_mut2(player2, token) {
+        p1.friends.push(player2.clone());
+        p2.friends.push(player1.clone());
+    } else {
+        player1.borrow_mut(token).friends.push(player2.clone());
+    }
 }
 
 #[derive(Default)]
@@ -58,16 +56,15 @@ struct Game<'brand> {
 }
 
 type GameRef<'brand> = Ref<'brand, Game<'brand>>;
-type GameWRef<'brand> = WRef<'brand, Game<'brand>>;
 
 fn create_player<'brand>(
     token: &mut GhostToken<'brand>,
+    heap: &mut GhostHeap<'brand, Player<'brand>>,
     game: &GameRef<'brand>,
     name: &str,
     health: i32,
 ) -> PlayerRef<'brand> {
-    let p = Ref::new(Player {
-        game: game.weak(),
+    let p = heap.alloc(Player {
         name: name.into(),
         health,
         friends: Default::default(),
@@ -77,26 +74,102 @@ fn create_player<'brand>(
     p
 }
 
-pub fn run_game() {
-    GhostToken::new(|mut token| {
-        let game = Ref::new(Default::default());
-        let t = &mut token;
+fn remove_player<'brand>(
+    token: &mut GhostToken<'brand>,
+    game: &GameRef<'brand>,
+    player: &PlayerRef<'brand>,
+) {
+    let friends = mem::take(&mut player.borrow_mut(token).friends);
+
+    for f in friends {
+        f.borrow_mut(token).friends.retain(|x| !x.ptr_eq(player));
+    }
+
+    game.borrow_mut(token).players.retain(|x| !x.ptr_eq(player));
+}
+
+pub struct GhostRcBackend;
 
-        let p1 = create_player(t, &game, "Eric", 10);
-        let p2 = create_player(t, &game, "Tom", 15);
-        let p3 = create_player(t, &game, "Carl", 17);
+/// Players refer to their friends with strong references, so the cycles
+/// between them are collected when the scenario is done.
+struct GhostRcModel<'brand> {
+    token: GhostToken<'brand>,
+    heap: GhostHeap<'brand, Player<'brand>>,
+    game: GameRef<'brand>,
+}
 
-        make_friends(t, &p1, &p2);
-        make_friends(t, &p1, &p3);
+impl<'brand> Model for GhostRcModel<'brand> {
+    type Player = PlayerRef<'brand>;
+
+    fn create_player(&mut self, name: &str, health: i32) -> Result<PlayerRef<'brand>, Error> {
+        Ok(create_player(
+            &mut self.token,
+            &mut self.heap,
+            &self.game,
+            name,
+            health,
+        ))
+    }
 
-        p2.borrow_mut(t).health = 20;
+    fn make_friends(
+        &mut self,
+        player1: &PlayerRef<'brand>,
+        player2: &PlayerRef<'brand>,
+    ) -> Result<(), Error> {
+        make_friends(&mut self.token, player1, player2);
+        Ok(())
+    }
+
+    fn with_name<R>(&self, player: &PlayerRef<'brand>, f: impl FnOnce(&str) -> R) -> R {
+        f(&player.borrow(&self.token).name)
+    }
+
+    fn health(&self, player: &PlayerRef<'brand>) -> i32 {
+        player.borrow(&self.token).health
+    }
+
+    fn set_health(&mut self, player: &PlayerRef<'brand>, health: i32) {
+        player.borrow_mut(&mut self.token).health = health;
+    }
 
-        for x in p1.borrow(t).friends.iter() {
-            println!(
-                "{}: {}",
-                x.upgrade().unwrap().borrow(t).name,
-                x.upgrade().unwrap().borrow(t).health
-            )
+    fn for_each_friend(&self, player: &PlayerRef<'brand>, mut f: impl FnMut(&PlayerRef<'brand>)) {
+        for x in player.borrow(&self.token).friends.iter() {
+            f(x)
         }
-    });
+    }
+
+    fn for_each_player(&self, mut f: impl FnMut(&PlayerRef<'brand>)) {
+        for x in self.game.borrow(&self.token).players.iter() {
+            f(x)
+        }
+    }
+
+    fn player_id(&self, player: &PlayerRef<'brand>) -> usize {
+        player.as_ptr() as usize
+    }
+
+    fn remove_player(&mut self, player: &PlayerRef<'brand>) -> Result<(), Error> {
+        remove_player(&mut self.token, &self.game, player);
+        Ok(())
+    }
+}
+
+impl ModelBackend for GhostRcBackend {
+    const NAME: &'static str = "Ghost Rc";
+
+    fn run<S: Scenario>(scenario: S) -> S::Output {
+        GhostToken::new(|token| {
+            let mut m = GhostRcModel {
+                token,
+                heap: GhostHeap::new(),
+                game: Ref::new(Default::default()),
+            };
+
+            let output = scenario.run(&mut m);
+            m.game.borrow_mut(&mut m.token).players.clear();
+            m.heap.collect(&mut m.token);
+            debug_assert!(m.heap.is_empty(), "Players leaked!");
+            output
+        })
+    }
 }
@@ -1,14 +1,9 @@
-use std::cell::RefCell;
-
-use rayon::join;
-
 use crate::ghost_cell::{GhostCell, GhostToken};
+use rayon::prelude::*;
 
 pub fn test() {
     GhostToken::new(|mut token| {
         let c = GhostCell::new(10);
-        let d = RefCell::new(10);
-        let cr = &c;
 
         rayon::join(
             || println!("{}", c.borrow(&token)),
@@ -16,5 +11,18 @@ pub fn test() {
         );
 
         println!("{}", c.borrow(&token));
+
+        // Parallel writes to disjoint cells
+        let cells: Vec<_> = (0..8).map(GhostCell::new).collect();
+
+        GhostCell::borrow_slice_mut(&cells, &mut token)
+            .par_chunks_mut(3)
+            .for_each(|chunk| {
+                for x in chunk {
+                    *x *= 10
+                }
+            });
+
+        println!("{:?}", GhostCell::borrow_slice(&cells, &token));
     })
 }
@@ -0,0 +1,28 @@
+// Lets the derive macros refer to this crate by name from inside it too
+extern crate self as rust_data_modelling;
+
+pub mod atomic_pool;
+pub mod branded_pool;
+pub mod cell;
+pub mod cell_pool;
+pub mod clear;
+pub mod dot;
+pub mod error;
+pub mod ghost_cell;
+pub mod ghost_gc;
+pub mod ghost_graph;
+pub mod ghost_list;
+pub mod ghost_pool;
+pub mod ghost_rc;
+pub mod ghost_thread;
+pub mod model;
+pub mod persist;
+pub mod ptr;
+pub mod ptr_collections;
+pub mod ref_cell;
+pub mod ref_count;
+pub mod ref_set;
+pub mod relation;
+pub mod static_cell;
+pub mod utils;
+pub mod weak;
@@ -1,45 +1,112 @@
-use crate::ghost_thread::test;
-
-mod cell;
-mod cell_pool;
-mod clear;
-mod ghost_cell;
-mod ghost_pool;
-mod ghost_rc;
-mod ghost_thread;
-mod ptr;
-mod ref_cell;
-mod ref_count;
-mod ref_set;
-mod static_cell;
-mod utils;
-
-fn main() -> Result<(), &'static str> {
-    println!("Rc:");
-    ref_count::run_game();
-    println!();
+use rust_data_modelling::{
+    cell::CellPoolBackend,
+    error::Error,
+    ghost_pool::GhostPoolBackend,
+    ghost_rc::GhostRcBackend,
+    ghost_thread,
+    model::{run_game, ModelBackend},
+    ref_cell::RefCellBackend,
+    ref_count::RcBackend,
+    static_cell::StaticPoolBackend,
+};
+use std::{env, process::ExitCode};
 
-    println!("RefCell:");
-    ref_cell::run_game();
-    println!();
+type Run = fn() -> Result<(), Error>;
 
-    println!("Cell pool:");
-    cell::run_game()?;
-    println!();
+/// The strategies that can be run from the command line, by name.
+const STRATEGIES: &[(&str, &str, Run)] = &[
+    ("rc", RcBackend::NAME, run_game::<RcBackend>),
+    ("ref-cell", RefCellBackend::NAME, run_game::<RefCellBackend>),
+    (
+        "cell-pool",
+        CellPoolBackend::NAME,
+        run_game::<CellPoolBackend>,
+    ),
+    (
+        "static-pool",
+        StaticPoolBackend::NAME,
+        run_game::<StaticPoolBackend>,
+    ),
+    ("ghost-rc", GhostRcBackend::NAME, run_game::<GhostRcBackend>),
+    (
+        "ghost-pool",
+        GhostPoolBackend::NAME,
+        run_game::<GhostPoolBackend>,
+    ),
+    ("ghost-thread", "Ghost thread", run_ghost_thread),
+];
 
-    println!("Cell static pool:");
-    static_cell::run_game()?;
-    println!();
+const USAGE: &str = "Usage: rust_data_modelling [command]
 
-    println!("Ghost Rc:");
-    ghost_rc::run_game();
-    println!();
+Commands:
+    list              List the available strategies
+    run <strategy>    Run the scenario of one strategy
+    all               Run the scenarios of all strategies (the default)";
+
+/// Exit code for invalid command lines.
+const USAGE_ERROR: u8 = 2;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|x| x.as_str()).collect();
+
+    match args.as_slice() {
+        [] | ["all"] => run_all(),
+        ["list"] => {
+            for (id, name, _) in STRATEGIES {
+                println!("{:<14}{}", id, name);
+            }
 
-    println!("Ghost pool:");
-    ghost_rc::run_game();
+            ExitCode::SUCCESS
+        }
+        ["run", id] => match STRATEGIES.iter().find(|(x, _, _)| x == id) {
+            Some((_, name, run)) => run_strategy(name, *run),
+            None => {
+                eprintln!(
+                    "Unknown strategy: {}. Run `list` to see all strategies.",
+                    id
+                );
+                ExitCode::from(USAGE_ERROR)
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::from(USAGE_ERROR)
+        }
+    }
+}
+
+/// Runs every strategy, even if one fails, and exits with the code of the
+/// first failure.
+fn run_all() -> ExitCode {
+    let mut result = ExitCode::SUCCESS;
+
+    for (_, name, run) in STRATEGIES {
+        let code = run_strategy(name, *run);
+
+        if result == ExitCode::SUCCESS {
+            result = code;
+        }
+    }
+
+    result
+}
+
+fn run_strategy(name: &str, run: Run) -> ExitCode {
+    println!("{}:", name);
+    let result = run();
     println!();
 
-    test();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{} failed: {}", name, e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
 
+fn run_ghost_thread() -> Result<(), Error> {
+    ghost_thread::test();
     Ok(())
 }
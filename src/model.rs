@@ -0,0 +1,211 @@
+use crate::error::Error;
+use std::collections::HashMap;
+
+/// The operations every data modelling strategy supports, so the same
+/// scenario can be run against each of them.
+pub trait Model {
+    type Player: Clone;
+
+    fn create_player(&mut self, name: &str, health: i32) -> Result<Self::Player, Error>;
+
+    /// Friendships form a set: making two players friends again has no
+    /// effect, and a player who is their own friend is listed once.
+    fn make_friends(&mut self, player1: &Self::Player, player2: &Self::Player)
+        -> Result<(), Error>;
+
+    fn with_name<R>(&self, player: &Self::Player, f: impl FnOnce(&str) -> R) -> R;
+
+    fn health(&self, player: &Self::Player) -> i32;
+
+    fn set_health(&mut self, player: &Self::Player, health: i32);
+
+    fn for_each_friend(&self, player: &Self::Player, f: impl FnMut(&Self::Player));
+
+    fn for_each_player(&self, f: impl FnMut(&Self::Player));
+
+    /// An id that's unique among the players currently in the game.
+    fn player_id(&self, player: &Self::Player) -> usize;
+
+    /// Removes the player from the game and from the friend lists of all
+    /// other players.
+    fn remove_player(&mut self, player: &Self::Player) -> Result<(), Error>;
+}
+
+/// Code that can run against any `Model`.
+///
+/// This is a trait rather than a closure as the model type is chosen by the
+/// backend, and closures can't be generic.
+pub trait Scenario {
+    type Output;
+
+    fn run<M: Model>(self, model: &mut M) -> Self::Output;
+}
+
+/// A data modelling strategy.
+pub trait ModelBackend {
+    const NAME: &'static str;
+
+    /// Creates an empty game and runs `scenario` on it.
+    fn run<S: Scenario>(scenario: S) -> S::Output;
+}
+
+/// Creates a few players, makes some of them friends and prints the friends
+/// of the first one, before and after removing one of them.
+pub struct RunGame;
+
+impl Scenario for RunGame {
+    type Output = Result<(), Error>;
+
+    fn run<M: Model>(self, m: &mut M) -> Self::Output {
+        let p1 = m.create_player("Eric", 10)?;
+        let p2 = m.create_player("Tom", 15)?;
+        let p3 = m.create_player("Carl", 17)?;
+
+        m.make_friends(&p1, &p2)?;
+        m.make_friends(&p1, &p3)?;
+
+        m.set_health(&p2, 20);
+        print_friends(m, &p1);
+
+        m.remove_player(&p3)?;
+        println!("After removing Carl:");
+        print_friends(m, &p1);
+
+        Ok(())
+    }
+}
+
+fn print_friends<M: Model>(m: &M, player: &M::Player) {
+    m.for_each_friend(player, |x| {
+        m.with_name(x, |name| println!("{}: {}", name, m.health(x)))
+    })
+}
+
+/// The players of `m`, and each friendship between them once, as the indexes
+/// of the two players with the lower one first. A player can be its own
+/// friend.
+pub(crate) fn friendships<M: Model>(m: &M) -> (Vec<M::Player>, Vec<(usize, usize)>) {
+    let mut players = Vec::new();
+    let mut indexes = HashMap::new();
+
+    m.for_each_player(|p| {
+        indexes.insert(m.player_id(p), players.len());
+        players.push(p.clone());
+    });
+
+    let mut friendships = Vec::new();
+
+    for (i, p) in players.iter().enumerate() {
+        m.for_each_friend(p, |f| match indexes.get(&m.player_id(f)) {
+            Some(&j) if i <= j => friendships.push((i, j)),
+            _ => {}
+        });
+    }
+
+    friendships.sort();
+    friendships.dedup();
+    (players, friendships)
+}
+
+pub fn run_game<B: ModelBackend>() -> Result<(), Error> {
+    B::run(RunGame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cell::CellPoolBackend, ghost_pool::GhostPoolBackend, ghost_rc::GhostRcBackend,
+        ref_cell::RefCellBackend, ref_count::RcBackend, static_cell::StaticPoolBackend,
+    };
+
+    /// The sorted names of the friends of the first player, before and after
+    /// removing the third one (who is also their own friend), and the sorted names of the remaining players.
+    struct RemovePlayer;
+
+    type Names = Vec<String>;
+
+    impl Scenario for RemovePlayer {
+        type Output = Result<(Names, Names, Names), Error>;
+
+        fn run<M: Model>(self, m: &mut M) -> Self::Output {
+            let p1 = m.create_player("Eric", 10)?;
+            let p2 = m.create_player("Tom", 15)?;
+            let p3 = m.create_player("Carl", 17)?;
+            m.make_friends(&p1, &p2)?;
+            m.make_friends(&p1, &p3)?;
+            m.make_friends(&p3, &p3)?;
+
+            let before = friend_names(m, &p1);
+            m.remove_player(&p3)?;
+            let after = friend_names(m, &p1);
+
+            let mut players = Vec::new();
+            m.for_each_player(|x| players.push(m.with_name(x, str::to_owned)));
+            players.sort();
+
+            Ok((before, after, players))
+        }
+    }
+
+    fn friend_names<M: Model>(m: &M, player: &M::Player) -> Names {
+        let mut names = Vec::new();
+        m.for_each_friend(player, |x| names.push(m.with_name(x, str::to_owned)));
+        names.sort();
+        names
+    }
+
+    fn check_remove_player<B: ModelBackend>() {
+        let (before, after, players) = B::run(RemovePlayer).unwrap();
+
+        assert_eq!(before, ["Carl", "Tom"], "{}", B::NAME);
+        assert_eq!(after, ["Tom"], "{}", B::NAME);
+        assert_eq!(players, ["Eric", "Tom"], "{}", B::NAME);
+    }
+
+    /// The number of friends of a player befriended twice by another player,
+    /// and then by themselves.
+    struct MakeFriends;
+
+    impl Scenario for MakeFriends {
+        type Output = Result<usize, Error>;
+
+        fn run<M: Model>(self, m: &mut M) -> Self::Output {
+            let p1 = m.create_player("Eric", 10)?;
+            let p2 = m.create_player("Tom", 15)?;
+            m.make_friends(&p1, &p2)?;
+            m.make_friends(&p1, &p2)?;
+            m.make_friends(&p2, &p1)?;
+            m.make_friends(&p1, &p1)?;
+            m.make_friends(&p1, &p1)?;
+
+            let mut count = 0;
+            m.for_each_friend(&p1, |_| count += 1);
+            Ok(count)
+        }
+    }
+
+    fn check_make_friends<B: ModelBackend>() {
+        assert_eq!(B::run(MakeFriends).unwrap(), 2, "{}", B::NAME);
+    }
+
+    #[test]
+    fn make_friends() {
+        check_make_friends::<RcBackend>();
+        check_make_friends::<RefCellBackend>();
+        check_make_friends::<CellPoolBackend>();
+        check_make_friends::<StaticPoolBackend>();
+        check_make_friends::<GhostRcBackend>();
+        check_make_friends::<GhostPoolBackend>();
+    }
+
+    #[test]
+    fn remove_player() {
+        check_remove_player::<RcBackend>();
+        check_remove_player::<RefCellBackend>();
+        check_remove_player::<CellPoolBackend>();
+        check_remove_player::<StaticPoolBackend>();
+        check_remove_player::<GhostRcBackend>();
+        check_remove_player::<GhostPoolBackend>();
+    }
+}
@@ -0,0 +1,331 @@
+//! Saving and loading entity graphs.
+//!
+//! `PoolData` saves the items of a `CellPool` whose references to each other
+//! are kept in `RefSet`s, storing the references as slot indexes, and re-links
+//! them when the items are allocated again on load. `GameData` does the same
+//! for the players of any `Model`, e.g. the `Rc` and ghost based ones.
+
+use crate::{
+    cell_pool::CellPool,
+    clear::Clear,
+    dot::Relations,
+    error::Error,
+    model::{self, Model},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersistError {
+    /// A reference to a saved item that doesn't exist, or an index that's
+    /// used by more than one saved item.
+    InvalidIndex { index: usize },
+    /// The saved item at `index` has `target` in a symmetric relation, but
+    /// not the other way around.
+    AsymmetricRelation { index: usize, target: usize },
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistError::InvalidIndex { index } => write!(f, "No saved item {}!", index),
+            PersistError::AsymmetricRelation { index, target } => write!(
+                f,
+                "Saved item {} is related to {}, but not the other way around!",
+                index, target
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PersistError {}
+
+/// A saved pool item.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemData<D> {
+    /// The slot index of the item in the saved pool.
+    pub index: usize,
+    pub data: D,
+    /// The slot indexes of the items in each relation, by relation name.
+    pub relations: Vec<(String, Vec<usize>)>,
+}
+
+/// The allocated items of a `CellPool`, in item list order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolData<D> {
+    pub items: Vec<ItemData<D>>,
+}
+
+impl<D> PoolData<D> {
+    /// Saves the items of `pool`, using `save` for everything but their
+    /// relations.
+    pub fn save<'t, T: Relations<'t>>(pool: &CellPool<T>, save: impl Fn(&T) -> D) -> Self {
+        let indexes = pool.slot_indexes();
+
+        let items = pool
+            .iter_indexed()
+            .map(|(index, x)| {
+                let mut relations = Vec::new();
+
+                x.relations(&mut |name, targets| {
+                    let targets = targets
+                        .iter()
+                        .filter_map(|y| indexes.get(&(y as *const T as usize)).copied())
+                        .collect();
+
+                    relations.push((name.to_owned(), targets));
+                });
+
+                ItemData {
+                    index,
+                    data: save(x),
+                    relations,
+                }
+            })
+            .collect();
+
+        Self { items }
+    }
+
+    /// Allocates the saved items in `pool`, sets each one up with `load` and
+    /// re-links their relations. Returns the items in the order they were
+    /// saved. Saved relations the items don't have are ignored.
+    ///
+    /// The indexes and the symmetric relations are checked before anything is
+    /// allocated, and if the pool runs full the items allocated so far are
+    /// freed again.
+    pub fn load<'t, T>(
+        &self,
+        pool: &'t CellPool<T>,
+        mut load: impl FnMut(&'t T, &D),
+    ) -> Result<Vec<&'t T>, Error>
+    where
+        T: Relations<'t> + Default + Clear,
+    {
+        let mut positions = HashMap::new();
+
+        for (i, x) in self.items.iter().enumerate() {
+            if positions.insert(x.index, i).is_some() {
+                return Err(PersistError::InvalidIndex { index: x.index }.into());
+            }
+        }
+
+        for x in self.items.iter() {
+            for &index in x.relations.iter().flat_map(|(_, targets)| targets) {
+                if !positions.contains_key(&index) {
+                    return Err(PersistError::InvalidIndex { index }.into());
+                }
+            }
+        }
+
+        for x in self.items.iter() {
+            for (name, targets) in x.relations.iter().filter(|(n, _)| T::is_symmetric(n)) {
+                for &target in targets.iter() {
+                    let back = self.items[positions[&target]]
+                        .relations
+                        .iter()
+                        .filter(|(n, _)| n == name)
+                        .any(|(_, targets)| targets.contains(&x.index));
+
+                    if !back {
+                        let index = x.index;
+                        return Err(PersistError::AsymmetricRelation { index, target }.into());
+                    }
+                }
+            }
+        }
+
+        let mut items = Vec::with_capacity(self.items.len());
+
+        for _ in self.items.iter() {
+            match pool.alloc() {
+                Ok(x) => items.push(x),
+                Err(e) => {
+                    for x in items {
+                        pool.free(x)?;
+                    }
+
+                    return Err(e.into());
+                }
+            }
+        }
+
+        for (x, data) in items.iter().zip(self.items.iter()) {
+            load(x, &data.data);
+        }
+
+        for (x, data) in items.iter().zip(self.items.iter()) {
+            x.relations(&mut |name, set| {
+                let targets = data.relations.iter().filter(|(n, _)| n == name);
+
+                for &index in targets.flat_map(|(_, targets)| targets) {
+                    set.add(items[positions[&index]]);
+                }
+            });
+        }
+
+        Ok(items)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PlayerData {
+    pub name: String,
+    pub health: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct GameData {
+    pub players: Vec<PlayerData>,
+    /// Each friendship is stored once, as the indexes of the two players with
+    /// the lower one first. A player can be its own friend.
+    pub friendships: Vec<(usize, usize)>,
+}
+
+impl GameData {
+    pub fn save<M: Model>(m: &M) -> Self {
+        let (players, friendships) = model::friendships(m);
+
+        Self {
+            players: players
+                .iter()
+                .map(|p| PlayerData {
+                    name: m.with_name(p, str::to_owned),
+                    health: m.health(p),
+                })
+                .collect(),
+            friendships,
+        }
+    }
+
+    /// Creates the saved players in `m` and makes them friends again. Returns
+    /// the players in the order they were saved.
+    ///
+    /// The indexes are checked before any player is created, and if creating
+    /// one fails the players created so far are removed again.
+    pub fn load<M: Model>(&self, m: &mut M) -> Result<Vec<M::Player>, Error> {
+        for &(a, b) in self.friendships.iter() {
+            for index in [a, b] {
+                if index >= self.players.len() {
+                    return Err(PersistError::InvalidIndex { index }.into());
+                }
+            }
+        }
+
+        let mut players = Vec::with_capacity(self.players.len());
+
+        for p in self.players.iter() {
+            match m.create_player(&p.name, p.health) {
+                Ok(x) => players.push(x),
+                Err(e) => {
+                    for x in players.iter() {
+                        m.remove_player(x)?;
+                    }
+
+                    return Err(e);
+                }
+            }
+        }
+
+        for &(a, b) in self.friendships.iter() {
+            m.make_friends(&players[a], &players[b])?;
+        }
+
+        Ok(players)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cell::CellPoolBackend,
+        ghost_pool::GhostPoolBackend,
+        ghost_rc::GhostRcBackend,
+        model::{ModelBackend, Scenario},
+        ref_cell::RefCellBackend,
+        ref_count::RcBackend,
+        static_cell::StaticPoolBackend,
+    };
+
+    fn game() -> GameData {
+        let player = |name: &str, health| PlayerData {
+            name: name.to_owned(),
+            health,
+        };
+
+        GameData {
+            players: vec![player("Eric", 10), player("Tom", 15), player("Carl", 17)],
+            friendships: vec![(0, 1), (0, 2)],
+        }
+    }
+
+    /// Loads the game and saves it again.
+    struct Reload(GameData);
+
+    impl Scenario for Reload {
+        type Output = Result<GameData, Error>;
+
+        fn run<M: Model>(self, m: &mut M) -> Self::Output {
+            self.0.load(m)?;
+            Ok(GameData::save(m))
+        }
+    }
+
+    /// Tries to load the game, returning the error and the number of players
+    /// left in the model.
+    struct LoadInvalid(GameData);
+
+    impl Scenario for LoadInvalid {
+        type Output = (Option<Error>, usize);
+
+        fn run<M: Model>(self, m: &mut M) -> Self::Output {
+            let error = self.0.load(m).err();
+            let mut count = 0;
+            m.for_each_player(|_| count += 1);
+            (error, count)
+        }
+    }
+
+    fn check_round_trip<B: ModelBackend>() {
+        let json = serde_json::to_string(&game()).unwrap();
+        let data = serde_json::from_str(&json).unwrap();
+        assert_eq!(B::run(Reload(data)), Ok(game()), "{}", B::NAME);
+
+        let mut data = game();
+        data.friendships.push((1, 3));
+
+        assert_eq!(
+            B::run(LoadInvalid(data)),
+            (Some(PersistError::InvalidIndex { index: 3 }.into()), 0),
+            "{}",
+            B::NAME
+        );
+    }
+
+    fn check_self_friendship<B: ModelBackend>() {
+        let mut data = game();
+        data.friendships.push((2, 2));
+        assert_eq!(B::run(Reload(data.clone())), Ok(data), "{}", B::NAME);
+    }
+
+    #[test]
+    fn self_friendship() {
+        check_self_friendship::<RcBackend>();
+        check_self_friendship::<RefCellBackend>();
+        check_self_friendship::<CellPoolBackend>();
+        check_self_friendship::<StaticPoolBackend>();
+        check_self_friendship::<GhostRcBackend>();
+        check_self_friendship::<GhostPoolBackend>();
+    }
+
+    #[test]
+    fn round_trip() {
+        check_round_trip::<RcBackend>();
+        check_round_trip::<RefCellBackend>();
+        check_round_trip::<CellPoolBackend>();
+        check_round_trip::<StaticPoolBackend>();
+        check_round_trip::<GhostRcBackend>();
+        check_round_trip::<GhostPoolBackend>();
+    }
+}
@@ -1,6 +1,7 @@
 use core::fmt;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
 use std::hash::Hasher;
-use std::{cmp::Ordering, fmt::Pointer};
 use std::{hash::Hash, ops::Deref};
 
 pub struct Ptr<'t, T> {
@@ -11,13 +12,26 @@ impl<T> Copy for Ptr<'_, T> {}
 
 impl<T> Clone for Ptr<'_, T> {
     fn clone(&self) -> Self {
-        Self { value: self.value }
+        *self
     }
 }
 
-impl<T> std::fmt::Debug for Ptr<'_, T> {
+/// Prints the address and the item, e.g. `Ptr(0x1000 => 42)`.
+impl<T: fmt::Debug> fmt::Debug for Ptr<'_, T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.value.fmt(f)
+        write!(f, "Ptr({:p} => ", self.value)?;
+        self.value.fmt(f)?;
+        write!(f, ")")
+    }
+}
+
+/// Prints a `Ptr` with `Debug` by address only, e.g. `Ptr(0x1000)`, for
+/// items that don't implement `Debug`.
+pub struct Addr<'t, T>(Ptr<'t, T>);
+
+impl<T> fmt::Debug for Addr<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Ptr({:p})", self.0.value)
     }
 }
 
@@ -35,6 +49,10 @@ impl<'t, T> Ptr<'t, T> {
     pub fn get(self) -> &'t T {
         self.value
     }
+
+    pub fn addr(self) -> Addr<'t, T> {
+        Addr(self)
+    }
 }
 
 impl<'t, T> From<&'t T> for Ptr<'t, T> {
@@ -53,7 +71,7 @@ impl<T> Deref for Ptr<'_, T> {
 
 impl<T> PartialEq for Ptr<'_, T> {
     fn eq(&self, other: &Self) -> bool {
-        self.value as *const T == other.value as *const T
+        std::ptr::eq(self.value, other.value)
     }
 }
 
@@ -76,3 +94,12 @@ impl<'t, T> Hash for Ptr<'t, T> {
         (self.value as *const T).hash(state);
     }
 }
+
+/// Lets collections keyed on `Ptr` be searched by address, with a reference
+/// of any lifetime. Compares and hashes like the `Ptr` itself.
+impl<T> Borrow<*const T> for Ptr<'_, T> {
+    fn borrow(&self) -> &*const T {
+        // Safe as `&T` and `*const T` have the same layout
+        unsafe { &*(&self.value as *const &T as *const *const T) }
+    }
+}
@@ -0,0 +1,264 @@
+//! Sets and maps keyed on the identity of the referenced items, for attaching
+//! side tables to items without adding fields to them.
+
+use crate::ptr::Ptr;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+};
+
+macro_rules! ptr_set {
+    ($(#[$attr:meta])* $name:ident, $set:ident) => {
+        $(#[$attr])*
+        pub struct $name<'t, T> {
+            set: $set<Ptr<'t, T>>,
+        }
+
+        impl<'t, T> $name<'t, T> {
+            pub fn new() -> Self {
+                Self { set: $set::new() }
+            }
+
+            /// Adds `v` to the set, returning `false` if it was already in it.
+            pub fn insert(&mut self, v: &'t T) -> bool {
+                self.set.insert(Ptr::new(v))
+            }
+
+            /// Removes `v` from the set, returning `false` if it wasn't in it.
+            pub fn remove(&mut self, v: &T) -> bool {
+                self.set.remove(&(v as *const T))
+            }
+
+            pub fn contains(&self, v: &T) -> bool {
+                self.set.contains(&(v as *const T))
+            }
+
+            pub fn len(&self) -> usize {
+                self.set.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.set.is_empty()
+            }
+
+            pub fn clear(&mut self) {
+                self.set.clear()
+            }
+
+            pub fn iter(&self) -> impl Iterator<Item = &'t T> + '_ {
+                self.set.iter().map(|x| x.get())
+            }
+        }
+
+        impl<'t, T> Default for $name<'t, T> {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl<'t, T> Clone for $name<'t, T> {
+            fn clone(&self) -> Self {
+                Self {
+                    set: self.set.clone(),
+                }
+            }
+        }
+
+        impl<'t, T: fmt::Debug> fmt::Debug for $name<'t, T> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_set().entries(self.set.iter()).finish()
+            }
+        }
+
+        impl<'t, T> Extend<&'t T> for $name<'t, T> {
+            fn extend<I: IntoIterator<Item = &'t T>>(&mut self, iter: I) {
+                self.set.extend(iter.into_iter().map(Ptr::new))
+            }
+        }
+
+        impl<'t, T> std::iter::FromIterator<&'t T> for $name<'t, T> {
+            fn from_iter<I: IntoIterator<Item = &'t T>>(iter: I) -> Self {
+                let mut s = Self::new();
+                s.extend(iter);
+                s
+            }
+        }
+    };
+}
+
+macro_rules! ptr_map {
+    ($(#[$attr:meta])* $name:ident, $map:ident) => {
+        $(#[$attr])*
+        pub struct $name<'t, K, V> {
+            map: $map<Ptr<'t, K>, V>,
+        }
+
+        impl<'t, K, V> $name<'t, K, V> {
+            pub fn new() -> Self {
+                Self { map: $map::new() }
+            }
+
+            /// Sets the value of `k`, returning the old one.
+            pub fn insert(&mut self, k: &'t K, v: V) -> Option<V> {
+                self.map.insert(Ptr::new(k), v)
+            }
+
+            pub fn remove(&mut self, k: &K) -> Option<V> {
+                self.map.remove(&(k as *const K))
+            }
+
+            pub fn get(&self, k: &K) -> Option<&V> {
+                self.map.get(&(k as *const K))
+            }
+
+            pub fn get_mut(&mut self, k: &K) -> Option<&mut V> {
+                self.map.get_mut(&(k as *const K))
+            }
+
+            /// The value of `k`, set to the result of `f` first if `k` has
+            /// none.
+            pub fn get_or_insert_with(&mut self, k: &'t K, f: impl FnOnce() -> V) -> &mut V {
+                self.map.entry(Ptr::new(k)).or_insert_with(f)
+            }
+
+            pub fn contains_key(&self, k: &K) -> bool {
+                self.map.contains_key(&(k as *const K))
+            }
+
+            pub fn len(&self) -> usize {
+                self.map.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.map.is_empty()
+            }
+
+            pub fn clear(&mut self) {
+                self.map.clear()
+            }
+
+            pub fn iter(&self) -> impl Iterator<Item = (&'t K, &V)> + '_ {
+                self.map.iter().map(|(k, v)| (k.get(), v))
+            }
+
+            pub fn iter_mut(&mut self) -> impl Iterator<Item = (&'t K, &mut V)> + '_ {
+                self.map.iter_mut().map(|(k, v)| (k.get(), v))
+            }
+
+            pub fn keys(&self) -> impl Iterator<Item = &'t K> + '_ {
+                self.map.keys().map(|k| k.get())
+            }
+
+            pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+                self.map.values()
+            }
+        }
+
+        impl<'t, K, V> Default for $name<'t, K, V> {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl<'t, K, V: Clone> Clone for $name<'t, K, V> {
+            fn clone(&self) -> Self {
+                Self {
+                    map: self.map.clone(),
+                }
+            }
+        }
+
+        impl<'t, K: fmt::Debug, V: fmt::Debug> fmt::Debug for $name<'t, K, V> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_map().entries(self.map.iter()).finish()
+            }
+        }
+
+        impl<'t, K, V> Extend<(&'t K, V)> for $name<'t, K, V> {
+            fn extend<I: IntoIterator<Item = (&'t K, V)>>(&mut self, iter: I) {
+                self.map
+                    .extend(iter.into_iter().map(|(k, v)| (Ptr::new(k), v)))
+            }
+        }
+
+        impl<'t, K, V> std::iter::FromIterator<(&'t K, V)> for $name<'t, K, V> {
+            fn from_iter<I: IntoIterator<Item = (&'t K, V)>>(iter: I) -> Self {
+                let mut m = Self::new();
+                m.extend(iter);
+                m
+            }
+        }
+    };
+}
+
+ptr_set!(
+    /// A hash set of references, compared by address.
+    PtrSet,
+    HashSet
+);
+
+ptr_set!(
+    /// A set of references ordered by address, so iterating it is
+    /// deterministic for a given memory layout, e.g. that of a pool.
+    PtrBTreeSet,
+    BTreeSet
+);
+
+ptr_map!(
+    /// A hash map keyed on references, compared by address.
+    PtrMap,
+    HashMap
+);
+
+ptr_map!(
+    /// A map keyed on references ordered by address, so iterating it is
+    /// deterministic for a given memory layout, e.g. that of a pool.
+    PtrBTreeMap,
+    BTreeMap
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Takes the item with a lifetime unrelated to the collections'.
+    fn remove_from_all(
+        x: &i32,
+        set: &mut PtrSet<'_, i32>,
+        btree_set: &mut PtrBTreeSet<'_, i32>,
+        map: &mut PtrMap<'_, i32, &str>,
+        btree_map: &mut PtrBTreeMap<'_, i32, &str>,
+    ) {
+        assert!(set.contains(x) && btree_set.contains(x));
+        assert!(map.contains_key(x) && btree_map.contains_key(x));
+        assert_eq!(map.get(x), Some(&"a"));
+        *btree_map.get_mut(x).unwrap() = "c";
+        assert_eq!(btree_map.get(x), Some(&"c"));
+
+        assert!(set.remove(x) && btree_set.remove(x));
+        assert_eq!(map.remove(x), Some("a"));
+        assert_eq!(btree_map.remove(x), Some("c"));
+    }
+
+    #[test]
+    fn lookup_by_address() {
+        let items = [1, 1];
+        let mut set: PtrSet<_> = items.iter().collect();
+        let mut btree_set: PtrBTreeSet<_> = items.iter().collect();
+        let mut map: PtrMap<_, _> = items.iter().zip(["a", "b"]).collect();
+        let mut btree_map: PtrBTreeMap<_, _> = items.iter().zip(["a", "b"]).collect();
+
+        assert!(!set.contains(&1));
+        remove_from_all(
+            &items[0],
+            &mut set,
+            &mut btree_set,
+            &mut map,
+            &mut btree_map,
+        );
+
+        assert!(set.contains(&items[1]) && btree_set.contains(&items[1]));
+        assert_eq!(map.get(&items[1]), Some(&"b"));
+        assert_eq!(btree_map.len(), 1);
+    }
+}
@@ -1,7 +1,10 @@
+use crate::{
+    error::Error,
+    model::{Model, ModelBackend, Scenario},
+};
 use std::cell::{Ref, RefCell, RefMut};
 
 struct Player<'t> {
-    game: GameRef<'t>,
     name: String,
     health: i32,
     friends: Vec<PlayerRef<'t>>,
@@ -15,63 +18,139 @@ struct PlayerRef<'t> {
 
 impl<'t> PlayerRef<'t> {
     fn borrow(&self) -> Ref<'_, Player<'t>> {
-        Ref::map(self.game.borrow(), |r| &r.players[self.index])
+        Ref::map(self.game.borrow(), |r| {
+            r.players[self.index].as_ref().expect("Player removed!")
+        })
     }
 
     fn borrow_mut(&self) -> RefMut<'_, Player<'t>> {
-        RefMut::map(self.game.borrow_mut(), |r| &mut r.players[self.index])
+        RefMut::map(self.game.borrow_mut(), |r| {
+            r.players[self.index].as_mut().expect("Player removed!")
+        })
     }
 
     fn make_friends(self, player2: PlayerRef<'t>) {
+        if self.is_friend(player2) {
+            return;
+        }
+
         self.borrow_mut().friends.push(player2);
-        player2.borrow_mut().friends.push(self);
+
+        if player2.index != self.index {
+            player2.borrow_mut().friends.push(self);
+        }
     }
-}
 
-struct Game<'t> {
-    players: Vec<Player<'t>>,
-}
+    fn is_friend(self, player2: PlayerRef<'t>) -> bool {
+        self.borrow()
+            .friends
+            .iter()
+            .any(|x| x.index == player2.index)
+    }
+
+    /// Removes the player, leaving an empty slot so the indexes of other
+    /// players stay valid.
+    fn remove(self) {
+        let p = self.game.borrow_mut().players[self.index].take();
 
-impl Default for Game<'_> {
-    fn default() -> Self {
-        Self {
-            players: Default::default(),
+        // A player can be their own friend, but is already gone
+        for f in p.into_iter().flat_map(|p| p.friends) {
+            if f.index != self.index {
+                f.borrow_mut().friends.retain(|x| x.index != self.index);
+            }
         }
     }
 }
 
+#[derive(Default)]
+struct Game<'t> {
+    players: Vec<Option<Player<'t>>>,
+}
+
 type GameRef<'t> = &'t RefCell<Game<'t>>;
 
 fn create_player<'t>(game: GameRef<'t>, name: &str, health: i32) -> PlayerRef<'t> {
     let p = Player {
-        game,
         name: name.into(),
         health,
         friends: Default::default(),
     };
 
     let mut g = game.borrow_mut();
-    g.players.push(p);
+    g.players.push(Some(p));
 
     PlayerRef {
-        game: game,
+        game,
         index: g.players.len() - 1,
     }
 }
 
-pub fn run_game() {
-    let game: RefCell<Game<'_>> = Default::default();
+pub struct RefCellBackend;
 
-    let p1 = create_player(&game, "Eric", 10);
-    let p2 = create_player(&game, "Tom", 15);
-    let p3 = create_player(&game, "Carl", 17);
+struct RefCellModel<'t> {
+    game: GameRef<'t>,
+}
+
+impl<'t> Model for RefCellModel<'t> {
+    type Player = PlayerRef<'t>;
+
+    fn create_player(&mut self, name: &str, health: i32) -> Result<PlayerRef<'t>, Error> {
+        Ok(create_player(self.game, name, health))
+    }
 
-    p1.make_friends(p2);
-    p1.make_friends(p3);
+    fn make_friends(
+        &mut self,
+        player1: &PlayerRef<'t>,
+        player2: &PlayerRef<'t>,
+    ) -> Result<(), Error> {
+        player1.make_friends(*player2);
+        Ok(())
+    }
+
+    fn with_name<R>(&self, player: &PlayerRef<'t>, f: impl FnOnce(&str) -> R) -> R {
+        f(&player.borrow().name)
+    }
+
+    fn health(&self, player: &PlayerRef<'t>) -> i32 {
+        player.borrow().health
+    }
+
+    fn set_health(&mut self, player: &PlayerRef<'t>, health: i32) {
+        player.borrow_mut().health = health;
+    }
+
+    fn for_each_friend(&self, player: &PlayerRef<'t>, mut f: impl FnMut(&PlayerRef<'t>)) {
+        for x in player.borrow().friends.iter() {
+            f(x)
+        }
+    }
+
+    fn for_each_player(&self, mut f: impl FnMut(&PlayerRef<'t>)) {
+        let game = self.game;
+        let count = game.borrow().players.len();
+
+        for index in 0..count {
+            if game.borrow().players[index].is_some() {
+                f(&PlayerRef { game, index })
+            }
+        }
+    }
+
+    fn player_id(&self, player: &PlayerRef<'t>) -> usize {
+        player.index
+    }
+
+    fn remove_player(&mut self, player: &PlayerRef<'t>) -> Result<(), Error> {
+        player.remove();
+        Ok(())
+    }
+}
 
-    p2.borrow_mut().health = 20;
+impl ModelBackend for RefCellBackend {
+    const NAME: &'static str = "RefCell";
 
-    for x in p1.borrow().friends.iter() {
-        println!("{}: {}", x.borrow().name, x.borrow().health)
+    fn run<S: Scenario>(scenario: S) -> S::Output {
+        let game: RefCell<Game<'_>> = Default::default();
+        scenario.run(&mut RefCellModel { game: &game })
     }
 }
@@ -1,10 +1,15 @@
+use crate::{
+    error::Error,
+    model::{Model, ModelBackend, Scenario},
+    weak::WeakRefs,
+};
 use std::{
     cell::RefCell,
+    mem,
     rc::{Rc, Weak},
 };
 
 struct Player {
-    game: GameWRef,
     name: String,
     health: i32,
     friends: Vec<PlayerWRef>,
@@ -14,8 +19,17 @@ type PlayerRef = Rc<RefCell<Player>>;
 type PlayerWRef = Weak<RefCell<Player>>;
 
 fn make_friends(player1: &PlayerRef, player2: &PlayerRef) {
-    player1.borrow_mut().friends.push(Rc::downgrade(player2));
-    player2.borrow_mut().friends.push(Rc::downgrade(player1));
+    let p2 = Rc::downgrade(player2);
+
+    if player1.borrow().friends.iter().any(|x| x.ptr_eq(&p2)) {
+        return;
+    }
+
+    player1.borrow_mut().friends.push(p2);
+
+    if !Rc::ptr_eq(player1, player2) {
+        player2.borrow_mut().friends.push(Rc::downgrade(player1));
+    }
 }
 
 #[derive(Default)]
@@ -24,11 +38,9 @@ struct Game {
 }
 
 type GameRef = Rc<RefCell<Game>>;
-type GameWRef = Weak<RefCell<Game>>;
 
 fn create_player(game: &GameRef, name: &str, health: i32) -> PlayerRef {
     let p: Rc<RefCell<Player>> = Rc::new(RefCell::new(Player {
-        game: Rc::downgrade(game),
         name: name.into(),
         health,
         friends: Default::default(),
@@ -38,23 +50,75 @@ fn create_player(game: &GameRef, name: &str, health: i32) -> PlayerRef {
     p
 }
 
-pub fn run_game() {
-    let game: GameRef = Default::default();
+fn remove_player(game: &GameRef, player: &PlayerRef) {
+    let friends = mem::take(&mut player.borrow_mut().friends);
+    let p = Rc::downgrade(player);
+
+    for f in friends.live() {
+        f.borrow_mut().friends.retain(|y| !y.ptr_eq(&p));
+    }
+
+    game.borrow_mut().players.retain(|x| !Rc::ptr_eq(x, player));
+}
+
+pub struct RcBackend;
+
+struct RcModel {
+    game: GameRef,
+}
+
+impl Model for RcModel {
+    type Player = PlayerRef;
 
-    let p1 = create_player(&game, "Eric", 10);
-    let p2 = create_player(&game, "Tom", 15);
-    let p3 = create_player(&game, "Carl", 17);
+    fn create_player(&mut self, name: &str, health: i32) -> Result<PlayerRef, Error> {
+        Ok(create_player(&self.game, name, health))
+    }
 
-    make_friends(&p1, &p2);
-    make_friends(&p1, &p3);
+    fn make_friends(&mut self, player1: &PlayerRef, player2: &PlayerRef) -> Result<(), Error> {
+        make_friends(player1, player2);
+        Ok(())
+    }
+
+    fn with_name<R>(&self, player: &PlayerRef, f: impl FnOnce(&str) -> R) -> R {
+        f(&player.borrow().name)
+    }
+
+    fn health(&self, player: &PlayerRef) -> i32 {
+        player.borrow().health
+    }
+
+    fn set_health(&mut self, player: &PlayerRef, health: i32) {
+        player.borrow_mut().health = health;
+    }
+
+    fn for_each_friend(&self, player: &PlayerRef, mut f: impl FnMut(&PlayerRef)) {
+        for x in player.borrow().friends.live() {
+            f(&x)
+        }
+    }
+
+    fn for_each_player(&self, mut f: impl FnMut(&PlayerRef)) {
+        for x in self.game.borrow().players.iter() {
+            f(x)
+        }
+    }
+
+    fn player_id(&self, player: &PlayerRef) -> usize {
+        Rc::as_ptr(player) as usize
+    }
+
+    fn remove_player(&mut self, player: &PlayerRef) -> Result<(), Error> {
+        remove_player(&self.game, player);
+        Ok(())
+    }
+}
 
-    p2.borrow_mut().health = 20;
+impl ModelBackend for RcBackend {
+    const NAME: &'static str = "Rc";
 
-    for x in p1.borrow().friends.iter() {
-        println!(
-            "{}: {}",
-            x.upgrade().unwrap().borrow().name,
-            x.upgrade().unwrap().borrow().health
-        )
+    fn run<S: Scenario>(scenario: S) -> S::Output {
+        scenario.run(&mut RcModel {
+            game: Default::default(),
+        })
     }
 }
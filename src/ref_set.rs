@@ -1,48 +1,246 @@
-use std::cell::Cell;
+use crate::clear::Clear;
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+    error::Error,
+    fmt, iter, mem,
+};
 
-#[derive(Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefSetError {
+    /// The item isn't in the set.
+    NotFound,
+}
+
+impl fmt::Display for RefSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefSetError::NotFound => write!(f, "Item not in set!"),
+        }
+    }
+}
+
+impl Error for RefSetError {}
+
+/// Items are identified by their address, like `Ptr` does.
+fn key<T>(v: &T) -> usize {
+    v as *const T as usize
+}
+
+struct Entries<'t, T> {
+    items: Vec<&'t T>,
+    /// The position of each item in `items`, by key.
+    indexes: HashMap<usize, usize>,
+}
+
+/// A set of references that grows on demand, with constant time `add`,
+/// `contains` and `remove` on average.
 pub struct RefSet<'t, T> {
-    items: Vec<Cell<Option<&'t T>>>,
+    entries: RefCell<Entries<'t, T>>,
 }
 
 impl<'t, T> RefSet<'t, T> {
-    pub fn new(capacity: usize) -> Self {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            items: vec![Default::default(); capacity],
+            entries: RefCell::new(Entries {
+                items: Vec::with_capacity(capacity),
+                indexes: HashMap::with_capacity(capacity),
+            }),
         }
     }
 
-    pub fn add(&self, v: &'t T) -> Result<(), &'static str> {
-        for x in self.items.iter() {
-            if x.get().is_none() {
-                x.set(Some(v));
-                return Ok(());
+    /// Adds `v` to the set, returning `false` if it was already in it.
+    pub fn add(&self, v: &'t T) -> bool {
+        let e = &mut *self.entries.borrow_mut();
+        let index = e.items.len();
+
+        match e.indexes.entry(key(v)) {
+            Entry::Vacant(x) => {
+                x.insert(index);
+                e.items.push(v);
+                true
             }
+            Entry::Occupied(_) => false,
         }
-
-        Err("Out of space!")
     }
 
-    pub fn remove(&self, v: &'t T) -> Result<(), &'static str> {
-        for x in self.items.iter() {
-            if let Some(a) = x.get() {
-                if a as *const T == v as *const T {
-                    x.set(None);
-                    return Ok(());
-                }
-            }
+    pub fn remove(&self, v: &T) -> Result<(), RefSetError> {
+        let e = &mut *self.entries.borrow_mut();
+        let index = e.indexes.remove(&key(v)).ok_or(RefSetError::NotFound)?;
+        e.items.swap_remove(index);
+
+        if let Some(&moved) = e.items.get(index) {
+            e.indexes.insert(key(moved), index);
         }
 
-        Err("Item not in set!")
+        Ok(())
     }
 
+    pub fn contains(&self, v: &T) -> bool {
+        self.entries.borrow().indexes.contains_key(&key(v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Empties the set. Use `Relation::unlink_left` or
+    /// `SymmetricRelation::unlink_all` instead for sets kept in sync with
+    /// others, so the items are removed from those too.
+    pub fn clear(&self) {
+        let e = &mut *self.entries.borrow_mut();
+        e.items.clear();
+        e.indexes.clear();
+    }
+
+    /// Iterates over the items without keeping the set borrowed, so it can
+    /// be changed in between. Items added meanwhile are visited, but removing
+    /// one moves the last item into its place, which is then skipped if the
+    /// removed item was already visited.
     pub fn iter(&self) -> impl Iterator<Item = &'t T> + '_ {
-        self.items.iter().filter_map(|x| x.get())
+        let mut index = 0;
+
+        iter::from_fn(move || {
+            let x = self.entries.borrow().items.get(index).copied();
+            index += 1;
+            x
+        })
+    }
+
+    /// The items in either set.
+    pub fn union(&self, other: &RefSet<'t, T>) -> RefSet<'t, T> {
+        let s = self.clone();
+
+        for x in other.iter() {
+            s.add(x);
+        }
+
+        s
+    }
+
+    /// The items in both sets.
+    pub fn intersection(&self, other: &RefSet<'_, T>) -> RefSet<'t, T> {
+        self.filter(|x| other.contains(x))
+    }
+
+    /// The items in this set but not in `other`.
+    pub fn difference(&self, other: &RefSet<'_, T>) -> RefSet<'t, T> {
+        self.filter(|x| !other.contains(x))
+    }
+
+    pub fn is_subset(&self, other: &RefSet<'_, T>) -> bool {
+        self.len() <= other.len() && self.iter().all(|x| other.contains(x))
+    }
+
+    fn filter(&self, mut f: impl FnMut(&T) -> bool) -> RefSet<'t, T> {
+        let s = RefSet::new();
+
+        for x in self.iter().filter(|x| f(x)) {
+            s.add(x);
+        }
+
+        s
+    }
+
+    /// Removes all items from the set, returning them.
+    pub(crate) fn take(&self) -> Vec<&'t T> {
+        let e = &mut *self.entries.borrow_mut();
+        e.indexes.clear();
+        mem::take(&mut e.items)
     }
 }
 
 impl<'t, T> Default for RefSet<'t, T> {
     fn default() -> Self {
-        Self::new(10)
+        Self::new()
+    }
+}
+
+impl<'t, T> Clear for RefSet<'t, T> {
+    fn clear(&self) {
+        RefSet::clear(self)
+    }
+}
+
+impl<'t, T> Clone for RefSet<'t, T> {
+    fn clone(&self) -> Self {
+        let e = self.entries.borrow();
+
+        Self {
+            entries: RefCell::new(Entries {
+                items: e.items.clone(),
+                indexes: e.indexes.clone(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The values of the items in `s`, sorted.
+    fn values(s: &RefSet<'_, i32>) -> Vec<i32> {
+        let mut v: Vec<_> = s.iter().copied().collect();
+        v.sort();
+        v
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let xs = [1, 2, 3];
+        let s = RefSet::new();
+
+        assert!(s.add(&xs[0]));
+        assert!(s.add(&xs[1]));
+        assert!(!s.add(&xs[0]));
+        assert_eq!(s.len(), 2);
+
+        // Items are identified by address, not by value
+        let other = 1;
+        assert!(!s.contains(&other));
+        assert_eq!(s.remove(&other), Err(RefSetError::NotFound));
+
+        assert_eq!(s.remove(&xs[0]), Ok(()));
+        assert_eq!(s.remove(&xs[0]), Err(RefSetError::NotFound));
+        assert!(s.add(&xs[0]));
+        assert!(s.add(&xs[2]));
+        assert_eq!(s.remove(&xs[1]), Ok(()));
+        assert!(s.contains(&xs[2]));
+        assert_eq!(values(&s), [1, 3]);
+    }
+
+    #[test]
+    fn set_operations() {
+        let xs = [1, 2, 3, 4];
+        let a = RefSet::new();
+        let b = RefSet::new();
+
+        for x in xs[..3].iter() {
+            a.add(x);
+        }
+
+        for x in xs[2..].iter() {
+            b.add(x);
+        }
+
+        assert_eq!(values(&a.union(&b)), [1, 2, 3, 4]);
+        assert_eq!(values(&a.intersection(&b)), [3]);
+        assert_eq!(values(&a.difference(&b)), [1, 2]);
+        assert_eq!(values(&b.difference(&a)), [4]);
+
+        assert!(!a.is_subset(&b));
+        assert!(a.intersection(&b).is_subset(&a));
+        assert!(a.is_subset(&a.union(&b)));
+        assert!(RefSet::new().is_subset(&a));
+        assert!(a.difference(&a).is_empty());
     }
 }
@@ -0,0 +1,237 @@
+//! Many-to-many relations that keep both directions in sync.
+
+use crate::ref_set::{RefSet, RefSetError};
+
+/// A many-to-many relation between `A`s and `B`s. The items on each side keep
+/// their related items in a `RefSet` of their own, found with the accessor
+/// functions, and the relation always updates both sets together.
+pub struct Relation<'t, A, B> {
+    left: for<'a> fn(&'a A) -> &'a RefSet<'t, B>,
+    right: for<'a> fn(&'a B) -> &'a RefSet<'t, A>,
+}
+
+impl<'t, A, B> Relation<'t, A, B> {
+    pub const fn new(
+        left: for<'a> fn(&'a A) -> &'a RefSet<'t, B>,
+        right: for<'a> fn(&'a B) -> &'a RefSet<'t, A>,
+    ) -> Self {
+        Self { left, right }
+    }
+
+    /// Relates `a` and `b`, returning `false` if they already were.
+    pub fn link(&self, a: &'t A, b: &'t B) -> bool {
+        (self.right)(b).add(a);
+        (self.left)(a).add(b)
+    }
+
+    pub fn unlink(&self, a: &A, b: &B) -> Result<(), RefSetError> {
+        (self.left)(a).remove(b)?;
+        (self.right)(b).remove(a)
+    }
+
+    /// Unrelates `a` from all `B`s.
+    pub fn unlink_left(&self, a: &A) {
+        for b in (self.left)(a).take() {
+            let _ = (self.right)(b).remove(a);
+        }
+    }
+
+    /// Unrelates `b` from all `A`s.
+    pub fn unlink_right(&self, b: &B) {
+        for a in (self.right)(b).take() {
+            let _ = (self.left)(a).remove(b);
+        }
+    }
+
+    pub fn contains(&self, a: &A, b: &B) -> bool {
+        (self.left)(a).contains(b)
+    }
+
+    /// The `B`s related to `a`.
+    pub fn right_of<'a>(&self, a: &'a A) -> impl Iterator<Item = &'t B> + 'a
+    where
+        't: 'a,
+    {
+        (self.left)(a).iter()
+    }
+
+    /// The `A`s related to `b`.
+    pub fn left_of<'a>(&self, b: &'a B) -> impl Iterator<Item = &'t A> + 'a
+    where
+        't: 'a,
+    {
+        (self.right)(b).iter()
+    }
+}
+
+impl<'t, A, B> Copy for Relation<'t, A, B> {}
+
+impl<'t, A, B> Clone for Relation<'t, A, B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+/// A many-to-many relation between items of the same type that always goes
+/// both ways, like friendship. Each item keeps its related items in a
+/// `RefSet`, found with the accessor function.
+pub struct SymmetricRelation<'t, T> {
+    set: for<'a> fn(&'a T) -> &'a RefSet<'t, T>,
+}
+
+impl<'t, T> SymmetricRelation<'t, T> {
+    pub const fn new(set: for<'a> fn(&'a T) -> &'a RefSet<'t, T>) -> Self {
+        Self { set }
+    }
+
+    /// Relates `a` and `b`, returning `false` if they already were. An item
+    /// can be related to itself.
+    pub fn link(&self, a: &'t T, b: &'t T) -> bool {
+        let added = (self.set)(a).add(b);
+
+        if !std::ptr::eq(a, b) {
+            (self.set)(b).add(a);
+        }
+
+        added
+    }
+
+    pub fn unlink(&self, a: &T, b: &T) -> Result<(), RefSetError> {
+        (self.set)(a).remove(b)?;
+
+        if !std::ptr::eq(a, b) {
+            (self.set)(b).remove(a)?;
+        }
+
+        Ok(())
+    }
+
+    /// Unrelates `a` from all items.
+    pub fn unlink_all(&self, a: &T) {
+        for b in (self.set)(a).take() {
+            if !std::ptr::eq(a, b) {
+                let _ = (self.set)(b).remove(a);
+            }
+        }
+    }
+
+    pub fn contains(&self, a: &T, b: &T) -> bool {
+        (self.set)(a).contains(b)
+    }
+
+    /// The items related to `a`.
+    pub fn related<'a>(&self, a: &'a T) -> impl Iterator<Item = &'t T> + 'a
+    where
+        't: 'a,
+    {
+        (self.set)(a).iter()
+    }
+}
+
+impl<'t, T> Copy for SymmetricRelation<'t, T> {}
+
+impl<'t, T> Clone for SymmetricRelation<'t, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Student<'t> {
+        courses: RefSet<'t, Course<'t>>,
+    }
+
+    #[derive(Default)]
+    struct Course<'t> {
+        students: RefSet<'t, Student<'t>>,
+    }
+
+    #[derive(Default)]
+    struct Person<'t> {
+        friends: RefSet<'t, Person<'t>>,
+    }
+
+    fn attends<'t>() -> Relation<'t, Student<'t>, Course<'t>> {
+        Relation::new(|s| &s.courses, |c| &c.students)
+    }
+
+    fn friends<'t>() -> SymmetricRelation<'t, Person<'t>> {
+        SymmetricRelation::new(|p| &p.friends)
+    }
+
+    #[test]
+    fn link_and_unlink() {
+        let students: [Student; 2] = Default::default();
+        let courses: [Course; 2] = Default::default();
+        let r = attends();
+
+        assert!(r.link(&students[0], &courses[0]));
+        assert!(!r.link(&students[0], &courses[0]));
+        assert!(r.link(&students[0], &courses[1]));
+        assert!(r.link(&students[1], &courses[0]));
+
+        assert!(r.contains(&students[0], &courses[1]));
+        assert!(!r.contains(&students[1], &courses[1]));
+        assert_eq!(r.right_of(&students[0]).count(), 2);
+        assert_eq!(r.left_of(&courses[0]).count(), 2);
+
+        assert_eq!(r.unlink(&students[0], &courses[0]), Ok(()));
+        assert_eq!(
+            r.unlink(&students[0], &courses[0]),
+            Err(RefSetError::NotFound)
+        );
+        assert!(!courses[0].students.contains(&students[0]));
+        assert!(courses[0].students.contains(&students[1]));
+    }
+
+    #[test]
+    fn unlink_sides() {
+        let students: [Student; 2] = Default::default();
+        let courses: [Course; 2] = Default::default();
+        let r = attends();
+
+        for s in students.iter() {
+            for c in courses.iter() {
+                r.link(s, c);
+            }
+        }
+
+        r.unlink_left(&students[0]);
+        assert!(students[0].courses.is_empty());
+        assert!(courses.iter().all(|c| c.students.len() == 1));
+
+        r.unlink_right(&courses[1]);
+        assert!(courses[1].students.is_empty());
+        assert_eq!(students[1].courses.len(), 1);
+        assert!(r.contains(&students[1], &courses[0]));
+    }
+
+    #[test]
+    fn symmetric() {
+        let people: [Person; 3] = Default::default();
+        let r = friends();
+
+        assert!(r.link(&people[0], &people[1]));
+        assert!(!r.link(&people[1], &people[0]));
+        assert!(r.link(&people[0], &people[2]));
+        assert!(r.link(&people[0], &people[0]));
+
+        assert!(r.contains(&people[1], &people[0]));
+        assert_eq!(r.related(&people[0]).count(), 3);
+        assert_eq!(people[0].friends.len(), 3);
+
+        assert_eq!(r.unlink(&people[1], &people[0]), Ok(()));
+        assert!(!r.contains(&people[0], &people[1]));
+        assert_eq!(r.unlink(&people[0], &people[0]), Ok(()));
+        assert_eq!(r.unlink(&people[0], &people[0]), Err(RefSetError::NotFound));
+
+        r.link(&people[2], &people[2]);
+        r.unlink_all(&people[2]);
+        assert!(people[2].friends.is_empty());
+        assert!(people[0].friends.is_empty());
+    }
+}
@@ -1,9 +1,16 @@
-use crate::{cell_pool::CellPool, clear::Clear};
-
-use super::ref_set::RefSet;
+use crate::{
+    cell_pool::{CellPool, PoolError},
+    clear::Clear,
+    dot::Relations,
+    error::Error,
+    model::{Model, ModelBackend, Scenario},
+};
+
+use super::{ref_set::RefSet, relation::SymmetricRelation};
 use std::cell::{Cell, RefCell};
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Clear)]
+#[clear(before = "Self::unlink_friends")]
 struct Player {
     game: Cell<Option<GameRef>>,
     name: RefCell<String>,
@@ -12,6 +19,8 @@ struct Player {
 }
 
 impl Player {
+    const FRIENDS: SymmetricRelation<'static, Self> = SymmetricRelation::new(|p| &p.friends);
+
     fn init(&self, game: GameRef, name: &str, health: i32) {
         self.game.set(Some(game));
         *self.name.borrow_mut() = name.to_owned();
@@ -19,14 +28,23 @@ impl Player {
     }
 
     fn make_friends(&'static self, player2: PlayerRef) {
-        self.friends.add(player2);
-        player2.friends.add(self);
+        Self::FRIENDS.link(self, player2);
+    }
+
+    /// Run before clearing, so a freed player isn't left behind in the friend
+    /// lists of other players.
+    fn unlink_friends(&self) {
+        Self::FRIENDS.unlink_all(self);
     }
 }
 
-impl Clear for Player {
-    fn clear(&self) {
-        self.name.borrow_mut().clear();
+impl Relations<'static> for Player {
+    fn relations(&self, f: &mut dyn FnMut(&str, &RefSet<'static, Self>)) {
+        f("friend", &self.friends)
+    }
+
+    fn is_symmetric(name: &str) -> bool {
+        name == "friend"
     }
 }
 
@@ -37,36 +55,135 @@ struct Game {
 }
 
 impl Game {
-    fn new(max_player_count: usize) -> Self {
+    fn new(chunk_size: usize) -> Self {
         Self {
-            players: CellPool::new(max_player_count),
+            players: CellPool::growable(chunk_size),
         }
     }
 
-    fn create_player(&'static self, name: &str, health: i32) -> Result<PlayerRef, &'static str> {
+    fn leak() -> GameRef {
+        Box::leak(Box::new(Game::new(100)))
+    }
+
+    fn create_player(&'static self, name: &str, health: i32) -> Result<PlayerRef, PoolError> {
         let p = self.players.alloc()?;
         p.init(self, name, health);
         Ok(p)
     }
+
+    /// Frees the player, removing it from the friend lists of other players.
+    fn remove_player(&self, player: PlayerRef) -> Result<(), PoolError> {
+        self.players.free(player)
+    }
 }
 
 type GameRef = &'static Game;
 
-pub fn run_game() -> Result<(), &'static str> {
-    let game = Box::leak(Box::new(Game::new(100)));
+pub struct StaticPoolBackend;
+
+struct StaticPoolModel {
+    game: GameRef,
+}
+
+impl Model for StaticPoolModel {
+    type Player = PlayerRef;
+
+    fn create_player(&mut self, name: &str, health: i32) -> Result<PlayerRef, Error> {
+        Ok(self.game.create_player(name, health)?)
+    }
+
+    fn make_friends(&mut self, player1: &PlayerRef, player2: &PlayerRef) -> Result<(), Error> {
+        player1.make_friends(player2);
+        Ok(())
+    }
+
+    fn with_name<R>(&self, player: &PlayerRef, f: impl FnOnce(&str) -> R) -> R {
+        f(&player.name.borrow())
+    }
 
-    let p1 = game.create_player("Eric", 10)?;
-    let p2 = game.create_player("Tom", 15)?;
-    let p3 = game.create_player("Carl", 17)?;
+    fn health(&self, player: &PlayerRef) -> i32 {
+        player.health.get()
+    }
 
-    p1.make_friends(p2);
-    p1.make_friends(p3);
+    fn set_health(&mut self, player: &PlayerRef, health: i32) {
+        player.health.set(health)
+    }
 
-    p2.health.set(20);
+    fn for_each_friend(&self, player: &PlayerRef, mut f: impl FnMut(&PlayerRef)) {
+        for x in player.friends.iter() {
+            f(&x)
+        }
+    }
 
-    for x in p1.friends.iter() {
-        println!("{}: {}", x.name.borrow(), x.health.get())
+    fn for_each_player(&self, mut f: impl FnMut(&PlayerRef)) {
+        for x in self.game.players.iter() {
+            f(&x)
+        }
     }
 
-    Ok(())
+    fn player_id(&self, player: &PlayerRef) -> usize {
+        *player as *const Player as usize
+    }
+
+    fn remove_player(&mut self, player: &PlayerRef) -> Result<(), Error> {
+        Ok(self.game.remove_player(player)?)
+    }
+}
+
+impl ModelBackend for StaticPoolBackend {
+    const NAME: &'static str = "Cell static pool";
+
+    /// Reuses a leaked game per thread, as the players reference it and each
+    /// other with `'static` lifetimes. The game is taken while a run is using
+    /// it, so a nested run leaks a new one instead.
+    fn run<S: Scenario>(scenario: S) -> S::Output {
+        let game = GAME.with(Cell::take).unwrap_or_else(Game::leak);
+        let output = scenario.run(&mut StaticPoolModel { game });
+        game.players.clear();
+        GAME.with(|g| g.set(Some(game)));
+        output
+    }
+}
+
+thread_local! {
+    static GAME: Cell<Option<GameRef>> = const { Cell::new(None) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts the players of the outer model after creating one in the inner.
+    struct Nested<'a, M> {
+        outer: &'a mut M,
+    }
+
+    impl<'a, O: Model> Scenario for Nested<'a, O> {
+        type Output = Result<usize, Error>;
+
+        fn run<M: Model>(self, m: &mut M) -> Self::Output {
+            m.create_player("Tom", 15)?;
+            let mut count = 0;
+            self.outer.for_each_player(|_| count += 1);
+            Ok(count)
+        }
+    }
+
+    struct Outer;
+
+    impl Scenario for Outer {
+        type Output = Result<(usize, usize), Error>;
+
+        fn run<M: Model>(self, m: &mut M) -> Self::Output {
+            let nested = StaticPoolBackend::run(Nested { outer: m })?;
+            let mut count = 0;
+            m.for_each_player(|_| count += 1);
+            Ok((nested, count))
+        }
+    }
+
+    #[test]
+    fn nested_runs_use_separate_games() {
+        assert_eq!(StaticPoolBackend::run(Outer).unwrap(), (0, 0));
+    }
 }
@@ -0,0 +1,81 @@
+//! Helpers for lists of weak references, which skip the references whose
+//! values have been dropped instead of panicking on them.
+
+use crate::ghost_gc::{GhostRc, GhostWeak};
+use std::{
+    rc::{Rc, Weak},
+    slice,
+};
+
+/// A weak reference that can be upgraded to a strong one.
+pub trait Upgrade {
+    type Strong;
+
+    fn upgrade(&self) -> Option<Self::Strong>;
+
+    fn is_dead(&self) -> bool;
+}
+
+impl<T> Upgrade for Weak<T> {
+    type Strong = Rc<T>;
+
+    fn upgrade(&self) -> Option<Rc<T>> {
+        Weak::upgrade(self)
+    }
+
+    fn is_dead(&self) -> bool {
+        self.strong_count() == 0
+    }
+}
+
+impl<'brand, T> Upgrade for GhostWeak<'brand, T> {
+    type Strong = GhostRc<'brand, T>;
+
+    fn upgrade(&self) -> Option<GhostRc<'brand, T>> {
+        GhostWeak::upgrade(self)
+    }
+
+    fn is_dead(&self) -> bool {
+        GhostWeak::is_dead(self)
+    }
+}
+
+/// Iterates over the upgraded live references of a list of weak references.
+pub struct Live<'a, W> {
+    iter: slice::Iter<'a, W>,
+}
+
+impl<'a, W: Upgrade> Iterator for Live<'a, W> {
+    type Item = W::Strong;
+
+    fn next(&mut self) -> Option<W::Strong> {
+        self.iter.find_map(|x| x.upgrade())
+    }
+}
+
+pub trait WeakRefs<W> {
+    /// The references whose values are still alive, upgraded.
+    fn live(&self) -> Live<'_, W>;
+
+    /// Removes the references whose values have been dropped, returning how
+    /// many they were.
+    fn prune_dead(&mut self) -> usize;
+
+    /// Like `live`, but removes the dead references first.
+    fn live_pruned(&mut self) -> Live<'_, W> {
+        self.prune_dead();
+        self.live()
+    }
+}
+
+impl<W: Upgrade> WeakRefs<W> for Vec<W> {
+    fn live(&self) -> Live<'_, W> {
+        Live { iter: self.iter() }
+    }
+
+    fn prune_dead(&mut self) -> usize {
+        let len = self.len();
+        self.retain(|x| !x.is_dead());
+        len - self.len()
+    }
+}
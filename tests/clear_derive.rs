@@ -0,0 +1,58 @@
+use rust_data_modelling::clear::{Clear, ClearMut};
+use std::cell::{Cell, RefCell};
+
+#[derive(Default, Clear)]
+struct Shared {
+    name: RefCell<String>,
+    #[clear(skip)]
+    id: Cell<u32>,
+    #[clear(with = "reset_health")]
+    health: Cell<i32>,
+}
+
+fn reset_health(health: &Cell<i32>) {
+    health.set(100)
+}
+
+#[derive(Default, ClearMut)]
+#[clear(before = "Owned::save_count")]
+struct Owned<T> {
+    items: Vec<T>,
+    name: String,
+    #[clear(skip)]
+    last_count: usize,
+}
+
+impl<T> Owned<T> {
+    fn save_count(&mut self) {
+        self.last_count = self.items.len()
+    }
+}
+
+#[test]
+fn derive_clear() {
+    let x = Shared::default();
+    *x.name.borrow_mut() = "Eric".to_owned();
+    x.id.set(3);
+    x.health.set(10);
+    x.clear();
+
+    assert_eq!(*x.name.borrow(), "");
+    assert_eq!(x.id.get(), 3);
+    assert_eq!(x.health.get(), 100);
+}
+
+#[test]
+fn derive_clear_mut() {
+    let mut x = Owned {
+        items: vec![1, 2, 3],
+        name: "Tom".to_owned(),
+        last_count: 0,
+    };
+
+    x.clear_mut();
+
+    assert!(x.items.is_empty());
+    assert_eq!(x.name, "");
+    assert_eq!(x.last_count, 3);
+}